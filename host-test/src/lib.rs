@@ -0,0 +1,41 @@
+// host-test/src/lib.rs
+//
+// Host build of the firmware modules that have no ESP-IDF dependencies, so their
+// unit tests run with a plain `cargo test` in this directory. The modules are
+// compiled from `../src` as they are, and must only refer to each other by their
+// full path (`crate::units::TempUnit`), never through the firmware's prelude.
+
+#[path = "../../src/alert_rules.rs"]
+pub mod alert_rules;
+
+#[path = "../../src/bus_backends.rs"]
+pub mod bus_backends;
+
+#[path = "../../src/config_store.rs"]
+pub mod config_store;
+
+#[path = "../../src/degreeday_totals.rs"]
+pub mod degreeday_totals;
+
+#[path = "../../src/influx_line.rs"]
+pub mod influx_line;
+
+#[path = "../../src/log_levels.rs"]
+pub mod log_levels;
+
+#[path = "../../src/pid.rs"]
+pub mod pid;
+
+#[path = "../../src/prometheus.rs"]
+pub mod prometheus;
+
+#[path = "../../src/push_template.rs"]
+pub mod push_template;
+
+#[path = "../../src/syslog_format.rs"]
+pub mod syslog_format;
+
+#[path = "../../src/trend.rs"]
+pub mod trend;
+
+// EOF
@@ -0,0 +1,332 @@
+// alert_rules.rs
+//
+// Alert rules and their state machine, kept apart from the polling and notification
+// glue in `alerts.rs`. A rule asks for a state from what is known about its sensor,
+// and `Alert::step` switches to it once the request has held for the hold-off time.
+
+use std::fmt;
+
+use anyhow::bail;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    Above,
+    Below,
+    Missing,
+    Stale,
+}
+
+impl AlertKind {
+    pub const ALL: [AlertKind; 4] = [
+        AlertKind::Above,
+        AlertKind::Below,
+        AlertKind::Missing,
+        AlertKind::Stale,
+    ];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            AlertKind::Above => "above",
+            AlertKind::Below => "below",
+            AlertKind::Missing => "missing",
+            AlertKind::Stale => "stale",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    pub sensor: String,
+    pub kind: AlertKind,
+    // threshold in Celsius for above/below, maximum age in seconds for stale
+    pub value: Option<f32>,
+}
+
+impl AlertRule {
+    /// Parse one `name:sensor:kind[:value]` entry, e.g. `freezer:28ff0102030405a6:above:-15`
+    pub fn parse(entry: &str) -> anyhow::Result<Self> {
+        let fields = entry.split(':').map(str::trim).collect::<Vec<_>>();
+        if !(3..=4).contains(&fields.len()) {
+            bail!("expected name:sensor:kind[:value]");
+        }
+
+        let name = fields[0];
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            bail!("name must be letters, digits, '_' or '-'");
+        }
+        if fields[1].is_empty() {
+            bail!("sensor id missing");
+        }
+        let Some(kind) = AlertKind::from_name(fields[2]) else {
+            bail!("unknown kind {:?}", fields[2]);
+        };
+        let value = match fields.get(3) {
+            Some(v) => Some(v.parse::<f32>()?),
+            None => None,
+        };
+        if value.is_none() && matches!(kind, AlertKind::Above | AlertKind::Below) {
+            bail!("{kind} needs a threshold");
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            sensor: fields[1].to_string(),
+            kind,
+            value,
+        })
+    }
+}
+
+/// What is known about a rule's sensor when it is evaluated
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SensorInput {
+    // last valid reading in Celsius and its unix time, kept while the sensor fails
+    pub reading: Option<(f32, i64)>,
+    // the latest measurement failed, or there has been no successful one since boot
+    pub failing: bool,
+    // the first poll cycle has completed, before that a missing reading means nothing
+    pub polled: bool,
+}
+
+pub struct Alert {
+    pub rule: AlertRule,
+    pub active: bool,
+    // unix time the current state was entered
+    pub since: Option<i64>,
+    // last valid reading in Celsius
+    pub value: Option<f32>,
+    // unix time the condition started asking for the other state
+    pending_since: Option<i64>,
+}
+
+impl Alert {
+    pub fn new(rule: AlertRule) -> Self {
+        Self {
+            rule,
+            active: false,
+            since: None,
+            value: None,
+            pending_since: None,
+        }
+    }
+
+    /// The state the condition asks for, None when it cannot be evaluated.
+    /// `stale_age` is the maximum reading age in seconds for stale rules.
+    pub fn evaluate(
+        &self,
+        input: &SensorInput,
+        hysteresis: f32,
+        stale_age: i64,
+        now: i64,
+    ) -> Option<bool> {
+        let hysteresis = hysteresis.abs();
+        match (self.rule.kind, input.reading, self.rule.value) {
+            (AlertKind::Above, Some((value, _)), Some(threshold)) => {
+                if value > threshold {
+                    Some(true)
+                } else if value <= threshold - hysteresis {
+                    Some(false)
+                } else {
+                    Some(self.active)
+                }
+            }
+            (AlertKind::Below, Some((value, _)), Some(threshold)) => {
+                if value < threshold {
+                    Some(true)
+                } else if value >= threshold + hysteresis {
+                    Some(false)
+                } else {
+                    Some(self.active)
+                }
+            }
+            (AlertKind::Missing, _, _) => Some(input.failing),
+            (AlertKind::Stale, _, _) if !input.polled => None,
+            (AlertKind::Stale, reading, _) => {
+                Some(reading.is_none_or(|(_, timestamp)| now - timestamp > stale_age))
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply the hold-off time, returns true if the alert changed state
+    pub fn step(&mut self, want: Option<bool>, now: i64, holdoff: i64) -> bool {
+        match want {
+            Some(want) if want != self.active => {
+                let pending = *self.pending_since.get_or_insert(now);
+                if now - pending >= holdoff {
+                    self.active = want;
+                    self.since = Some(now);
+                    self.pending_since = None;
+                    return true;
+                }
+            }
+            _ => self.pending_since = None,
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENSOR: &str = "28ff0102030405a6";
+
+    fn alert(entry: &str) -> Alert {
+        Alert::new(AlertRule::parse(entry).unwrap())
+    }
+
+    fn reading(value: f32, timestamp: i64) -> SensorInput {
+        SensorInput {
+            reading: Some((value, timestamp)),
+            failing: false,
+            polled: true,
+        }
+    }
+
+    // evaluate and step with no hold-off, returns the state afterwards
+    fn feed(alert: &mut Alert, input: SensorInput, now: i64) -> bool {
+        let want = alert.evaluate(&input, 1.0, 60, now);
+        alert.step(want, now, 0);
+        alert.active
+    }
+
+    #[test]
+    fn parse_rules() {
+        let rule = AlertRule::parse(" freezer : 28ff0102030405a6 : Above : -15 ").unwrap();
+        assert_eq!(
+            rule,
+            AlertRule {
+                name: "freezer".to_string(),
+                sensor: SENSOR.to_string(),
+                kind: AlertKind::Above,
+                value: Some(-15.0),
+            }
+        );
+        let rule = AlertRule::parse("gone:28ff0102030405a6:missing").unwrap();
+        assert_eq!((rule.kind, rule.value), (AlertKind::Missing, None));
+        let rule = AlertRule::parse("old:28ff0102030405a6:stale:600").unwrap();
+        assert_eq!((rule.kind, rule.value), (AlertKind::Stale, Some(600.0)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for entry in [
+            "freezer:28ff0102030405a6",
+            "freezer:28ff0102030405a6:above:1:2",
+            "free zer:28ff0102030405a6:above:1",
+            ":28ff0102030405a6:above:1",
+            "freezer::above:1",
+            "freezer:28ff0102030405a6:hot:1",
+            "freezer:28ff0102030405a6:above",
+            "freezer:28ff0102030405a6:below:cold",
+        ] {
+            assert!(AlertRule::parse(entry).is_err(), "{entry:?} accepted");
+        }
+    }
+
+    #[test]
+    fn above_with_hysteresis() {
+        let mut a = alert("boiler:28ff0102030405a6:above:80");
+        assert!(!feed(&mut a, reading(79.9, 0), 0));
+        assert!(feed(&mut a, reading(80.5, 0), 1));
+        // back below the threshold, but not by the hysteresis
+        assert!(feed(&mut a, reading(79.5, 0), 2));
+        assert!(!feed(&mut a, reading(79.0, 0), 3));
+        assert!(!feed(&mut a, reading(79.5, 0), 4));
+    }
+
+    #[test]
+    fn below_with_hysteresis() {
+        let mut a = alert("pipes:28ff0102030405a6:below:2");
+        assert!(!feed(&mut a, reading(2.0, 0), 0));
+        assert!(feed(&mut a, reading(1.5, 0), 1));
+        assert!(feed(&mut a, reading(2.5, 0), 2));
+        assert!(!feed(&mut a, reading(3.0, 0), 3));
+        assert!(!feed(&mut a, reading(2.5, 0), 4));
+    }
+
+    #[test]
+    fn threshold_without_reading() {
+        let a = alert("boiler:28ff0102030405a6:above:80");
+        assert_eq!(a.evaluate(&SensorInput::default(), 1.0, 60, 0), None);
+    }
+
+    #[test]
+    fn missing_follows_failures() {
+        let mut a = alert("gone:28ff0102030405a6:missing");
+        assert!(!feed(&mut a, reading(20.0, 0), 0));
+        // the last good reading is still there while the sensor fails
+        let failing = SensorInput {
+            failing: true,
+            ..reading(20.0, 0)
+        };
+        assert!(feed(&mut a, failing, 10));
+        assert!(!feed(&mut a, reading(20.5, 20), 20));
+        // never read since boot
+        let never = SensorInput {
+            reading: None,
+            failing: true,
+            polled: true,
+        };
+        assert!(feed(&mut a, never, 30));
+    }
+
+    #[test]
+    fn stale_reading() {
+        let mut a = alert("old:28ff0102030405a6:stale");
+        assert!(!feed(&mut a, reading(20.0, 100), 160));
+        assert!(feed(&mut a, reading(20.0, 100), 161));
+        assert!(!feed(&mut a, reading(20.0, 161), 162));
+        let gone = SensorInput {
+            polled: true,
+            ..SensorInput::default()
+        };
+        assert!(feed(&mut a, gone, 163));
+    }
+
+    #[test]
+    fn stale_waits_for_first_poll() {
+        // e.g. waiting for WiFi and NTP, no reading is not yet a stale one
+        let mut a = alert("old:28ff0102030405a6:stale");
+        assert_eq!(a.evaluate(&SensorInput::default(), 1.0, 60, 1000), None);
+        assert!(!feed(&mut a, SensorInput::default(), 1000));
+        assert!(!a.step(None, 5000, 60));
+        assert!(!a.active);
+    }
+
+    #[test]
+    fn holdoff() {
+        let mut a = alert("boiler:28ff0102030405a6:above:80");
+        let hot = reading(85.0, 0);
+        let want = a.evaluate(&hot, 1.0, 60, 100);
+        assert!(!a.step(want, 100, 30));
+        assert!(!a.step(want, 129, 30));
+        // the condition went away in between, the hold-off starts over
+        assert!(!a.step(Some(false), 130, 30));
+        assert!(!a.step(want, 140, 30));
+        assert!(a.step(want, 170, 30));
+        assert_eq!((a.active, a.since), (true, Some(170)));
+    }
+}
+
+// EOF
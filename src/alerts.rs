@@ -0,0 +1,198 @@
+// alerts.rs
+//
+// On-device alert rules, so freezer or boiler alarms keep working without a home
+// automation server. The rules themselves are in `alert_rules.rs`, this is the task
+// that feeds them the readings and sensor failures every few seconds and sends out
+// the state changes on MQTT and to the webhook.
+
+use std::collections::VecDeque;
+
+use crate::*;
+
+const ALERT_TICK_S: u64 = 5;
+// notifications kept for MQTT while it is disconnected
+const ALERT_QUEUE_MAX: usize = 32;
+// stale rules without a value fire when the reading is older than this many poll intervals
+const ALERT_STALE_POLLS: u64 = 3;
+
+/// State changes waiting for the MQTT sender, oldest first
+pub type AlertQueue = Mutex<VecDeque<AlertStatus>>;
+
+impl AlertRule {
+    /// Parse the `alert_rules` config value, entries separated by `;`. Invalid ones are logged and skipped.
+    pub fn parse_list(rules: &str) -> Vec<Self> {
+        rules
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| match Self::parse(entry) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    error!("Invalid alert rule {entry:?}: {e:#}");
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Alert state as reported over HTTP, MQTT and the webhook, in the display unit
+#[derive(Clone, Debug, Serialize)]
+pub struct AlertStatus {
+    pub name: String,
+    pub sensor: String,
+    pub kind: AlertKind,
+    // temperature for above/below, seconds for stale
+    pub threshold: Option<f32>,
+    pub unit: TempUnit,
+    pub active: bool,
+    pub since: Option<i64>,
+    pub value: Option<f32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AlertValues {
+    pub alerts: Vec<AlertStatus>,
+}
+
+#[derive(Serialize)]
+struct AlertNotification<'a> {
+    device: &'a str,
+    #[serde(flatten)]
+    alert: &'a AlertStatus,
+}
+
+impl Alert {
+    fn stale_age(&self, config: &MyConfig) -> i64 {
+        match self.rule.value {
+            Some(age) => age as i64,
+            None => (ALERT_STALE_POLLS * config.delay) as i64,
+        }
+    }
+
+    pub fn status(&self, config: &MyConfig, unit: TempUnit) -> AlertStatus {
+        let threshold = match self.rule.kind {
+            AlertKind::Above | AlertKind::Below => self.rule.value.map(|t| unit.from_celsius(t)),
+            AlertKind::Stale => Some(self.stale_age(config) as f32),
+            AlertKind::Missing => None,
+        };
+        AlertStatus {
+            name: self.rule.name.clone(),
+            sensor: self.rule.sensor.clone(),
+            kind: self.rule.kind,
+            threshold,
+            unit,
+            active: self.active,
+            since: self.since,
+            value: self.value.map(|v| unit.from_celsius(v)),
+        }
+    }
+}
+
+pub async fn run_alerts(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if state.alerts.read().await.is_empty() {
+        info!("No alert rules configured.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let holdoff = state.config.alert_holdoff as i64;
+    let unit = state.config.unit;
+    loop {
+        let now = Utc::now().timestamp();
+        let mut changed = Vec::new();
+        {
+            let data = state.data.read().await;
+            let stats = state.sensor_stats.read().await;
+            let mut alerts = state.alerts.write().await;
+            for alert in alerts.iter_mut() {
+                let sensor = &alert.rule.sensor;
+                let reading = data
+                    .temperatures
+                    .iter()
+                    .find(|t| t.value > NO_TEMP && t.sensor.eq_ignore_ascii_case(sensor));
+                if let Some(t) = reading {
+                    alert.value = Some(t.value);
+                }
+                // until the first poll cycle has completed nothing is known yet
+                let polled = data.timestamp > 0;
+                let failing = match stats.iter().find(|(id, _)| id.eq_ignore_ascii_case(sensor)) {
+                    Some((_, s)) => {
+                        s.consecutive_failures > 0 || (polled && s.last_success.is_none())
+                    }
+                    None => polled,
+                };
+                let input = SensorInput {
+                    reading: reading.map(|t| (t.value, t.timestamp)),
+                    failing,
+                    polled,
+                };
+                let want = alert.evaluate(
+                    &input,
+                    state.config.alert_hysteresis,
+                    alert.stale_age(&state.config),
+                    now,
+                );
+                if alert.step(want, now, holdoff) {
+                    info!(
+                        "Alert {} {} (sensor {}, {}, value {:?})",
+                        alert.rule.name,
+                        if alert.active { "firing" } else { "cleared" },
+                        alert.rule.sensor,
+                        alert.rule.kind,
+                        alert.value
+                    );
+                    changed.push(alert.status(&state.config, unit));
+                }
+            }
+        }
+
+        if !changed.is_empty() {
+            Box::pin(notify(&state, changed)).await;
+        }
+
+        sleep(Duration::from_secs(ALERT_TICK_S)).await;
+    }
+}
+
+async fn notify(state: &MyState, changed: Vec<AlertStatus>) {
+    if state.config.mqtt_enable {
+        let mut queue = state.alert_queue.lock().await;
+        for alert in changed.iter() {
+            if queue.len() >= ALERT_QUEUE_MAX {
+                queue.pop_front();
+            }
+            queue.push_back(alert.clone());
+        }
+    }
+
+    let url = state.config.alert_webhook.trim();
+    if url.is_empty() || state.ap_mode {
+        return;
+    }
+    let device = state.myid.read().await.clone();
+    for alert in changed.iter() {
+        let body = match serde_json::to_vec(&AlertNotification {
+            device: &device,
+            alert,
+        }) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Alert webhook encode error: {e}");
+                continue;
+            }
+        };
+        match state
+            .http
+            .post(url, "application/json", Vec::new(), body)
+            .await
+        {
+            Ok(status) if (200..300).contains(&status) => {}
+            Ok(status) => error!("Alert webhook returned HTTP {status}"),
+            Err(e) => error!("Alert webhook error: {e:#}"),
+        }
+    }
+}
+
+// EOF
@@ -0,0 +1,141 @@
+// announce.rs
+//
+// Serverless LAN output: after each poll cycle the new readings are announced as
+// compact JSON datagrams to a UDP multicast group (or a broadcast address), so any
+// listener on the segment can collect them without a broker.
+
+use tokio::net::UdpSocket;
+
+use crate::*;
+
+const ANNOUNCE_TICK_S: u64 = 5;
+// keep datagrams within a typical MTU, larger cycles are split
+const ANNOUNCE_MAX_DATAGRAM: usize = 1400;
+
+/// One reading in an announcement, keys kept short for small datagrams
+#[derive(Clone, Debug, Serialize)]
+struct AnnouncedReading {
+    #[serde(rename = "s")]
+    sensor: String,
+    #[serde(rename = "a", skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    #[serde(rename = "v")]
+    value: f32,
+    #[serde(rename = "t")]
+    timestamp: i64,
+}
+
+/// Announcement datagram, e.g.
+/// `{"d":"esp32temp-A0B1C2D3E4F5","u":"C","t":1700000000,"r":[{"s":"28ff...","a":"freezer","v":-18.5,"t":1700000000}]}`
+#[derive(Clone, Debug, Serialize)]
+struct Announcement {
+    #[serde(rename = "d")]
+    device: String,
+    #[serde(rename = "u")]
+    unit: TempUnit,
+    #[serde(rename = "t")]
+    timestamp: i64,
+    #[serde(rename = "r")]
+    readings: Vec<AnnouncedReading>,
+}
+
+impl Announcement {
+    /// Serialize into one or more datagrams of at most ANNOUNCE_MAX_DATAGRAM bytes
+    fn datagrams(mut self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut out = Vec::new();
+        let mut rest = std::mem::take(&mut self.readings);
+        while !rest.is_empty() {
+            // as many readings as fit, at least one
+            let mut n = rest.len();
+            loop {
+                self.readings = rest[..n].to_vec();
+                let json = serde_json::to_vec(&self)?;
+                if json.len() <= ANNOUNCE_MAX_DATAGRAM || n == 1 {
+                    out.push(json);
+                    break;
+                }
+                n -= 1;
+            }
+            rest.drain(..n);
+        }
+        Ok(out)
+    }
+}
+
+async fn announce_socket(config: &MyConfig) -> anyhow::Result<(UdpSocket, net::SocketAddrV4)> {
+    let addr = match config.announce_addr.trim().parse::<net::Ipv4Addr>() {
+        Ok(addr) => addr,
+        Err(e) => bail!("invalid announce address {}: {e}", config.announce_addr),
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    if addr.is_multicast() {
+        socket.set_multicast_ttl_v4(config.announce_ttl.max(1))?;
+    } else {
+        socket.set_broadcast(true)?;
+    }
+    Ok((socket, net::SocketAddrV4::new(addr, config.announce_port)))
+}
+
+pub async fn run_announce(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if state.ap_mode || !state.config.announce_enable {
+        info!("UDP announcements are disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let config = &state.config;
+    let unit = config.unit;
+    let (socket, dest) = match announce_socket(config).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("UDP announcements disabled: {e:#}");
+            loop {
+                sleep(Duration::from_secs(3600)).await;
+            }
+        }
+    };
+    info!("UDP announcements to {dest}");
+
+    let mut readings = NewReadings::default();
+    loop {
+        sleep(Duration::from_secs(ANNOUNCE_TICK_S)).await;
+
+        let Some((cycle_ts, fresh)) = readings.take(&state).await else {
+            continue;
+        };
+        if fresh.is_empty() {
+            continue;
+        }
+
+        let announcement = Announcement {
+            device: state.myid.read().await.clone(),
+            unit,
+            timestamp: cycle_ts,
+            readings: fresh
+                .iter()
+                .map(|t| AnnouncedReading {
+                    sensor: t.sensor.clone(),
+                    alias: config.sensor_alias(&t.sensor).map(str::to_string),
+                    value: unit.from_celsius(t.value),
+                    timestamp: t.timestamp,
+                })
+                .collect(),
+        };
+        let datagrams = match announcement.datagrams() {
+            Ok(d) => d,
+            Err(e) => {
+                error!("UDP announcement error: {e:#}");
+                continue;
+            }
+        };
+        for datagram in datagrams.iter() {
+            if let Err(e) = socket.send_to(datagram, dest).await {
+                error!("UDP announcement to {dest} failed: {e}");
+                break;
+            }
+        }
+    }
+}
+
+// EOF
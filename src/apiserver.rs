@@ -1,9 +1,11 @@
 // apiserver.rs
 
+use std::convert::Infallible;
+
 use axum::{
     Json, Router,
     body::Body,
-    extract::{Form, State},
+    extract::{Form, Path, Query, State},
     http::{Response, StatusCode, header},
     response::{Html, IntoResponse},
     routing::*,
@@ -15,9 +17,14 @@ use esp_idf_svc::{
     io,
     ota::EspOta,
 };
+use futures_util::{StreamExt, stream};
 
 use crate::*;
 
+// covers the poller finishing its current cycle plus the requested measurement
+const MEASURE_TIMEOUT_S: u64 = 30;
+const LOG_FOLLOW_POLL_MS: u64 = 500;
+
 macro_rules! static_handler {
     ($fn_name:ident, $path:literal, $content_type:literal, $bytes:expr) => {
         async fn $fn_name(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
@@ -73,7 +80,22 @@ pub async fn run_api_server(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()>
         .route("/index.css", get(get_indexcss))
         .route("/uptime", get(get_uptime))
         .route("/sensors", get(get_sensors))
+        .route("/sensors/{rom_id}", get(get_sensor_detail))
         .route("/temp", get(get_temp))
+        .route("/degreedays", get(get_degree_days))
+        .route("/measure", post(post_measure).options(options))
+        .route(
+            "/thermostat",
+            get(get_thermostat).post(post_thermostat).options(options),
+        )
+        .route("/pwm", get(get_pwm).post(post_pwm).options(options))
+        .route("/alerts", get(get_alerts))
+        .route("/metrics", get(get_metrics))
+        .route("/log", get(get_log))
+        .route(
+            "/loglevel",
+            get(get_loglevel).post(post_loglevel).options(options),
+        )
         .route(
             "/config",
             get(get_config).post(post_config).options(options),
@@ -108,7 +130,8 @@ pub async fn get_index(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<
     info!("#{cnt} get_index()");
 
     let value_tuple: (&str, &dyn Any) = ("ota_slot", &state.ota_slot.clone());
-    let index = match state.config.clone().render_with_values(&value_tuple) {
+    let config = state.saved_config.read().await.clone();
+    let index = match config.render_with_values(&value_tuple) {
         Err(e) => {
             let err_msg = format!("Index template error: {e:?}\n");
             error!("{err_msg}");
@@ -153,12 +176,94 @@ pub async fn get_sensors(
     (StatusCode::OK, Json(sensors))
 }
 
+#[derive(Clone, Debug, Serialize)]
+pub struct SensorDetail {
+    pub sensor: String,
+    pub iopin: String,
+    pub family: String,
+    pub parasite: Option<bool>,
+    pub scratchpad: Option<ScratchpadInfo>,
+    pub scratchpad_error: Option<String>,
+    pub unit: TempUnit,
+    pub last_reading: Option<TempData>,
+    pub stats: SensorStats,
+}
+
+pub async fn get_sensor_detail(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Path(rom_id): Path<String>,
+    Query(query): Query<UnitQuery>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_sensor_detail({rom_id})");
+
+    let onewires = state.sensors.read().await;
+    let Some((onew, device)) = onewires.iter().find_map(|onew| {
+        onew.ids
+            .iter()
+            .find(|id| format_device_id(id).eq_ignore_ascii_case(&rom_id))
+            .map(|id| (onew, *id))
+    }) else {
+        let msg = format!("No such sensor: {rom_id}");
+        error!("{msg}");
+        return (StatusCode::NOT_FOUND, msg).into_response();
+    };
+    let sensor = format_device_id(&device);
+
+    // the bus mutex keeps this from interfering with a poll cycle
+    let (scratchpad, scratchpad_error, live_parasite) =
+        match Box::pin(inspect_sensor(onew, &device)).await {
+            Ok((scratchpad, parasite)) => (Some(scratchpad), None, Some(parasite)),
+            Err(e) => {
+                error!("Sensor {sensor} scratchpad read error: {e:#}");
+                (None, Some(format!("{e:#}")), None)
+            }
+        };
+
+    let unit = query.unit.unwrap_or(state.config.unit);
+    let last_reading = state
+        .data
+        .read()
+        .await
+        .temperatures
+        .iter()
+        .find(|t| t.sensor == sensor && t.value > NO_TEMP)
+        .map(|t| t.in_unit(unit));
+    let stats = state
+        .sensor_stats
+        .read()
+        .await
+        .get(&sensor)
+        .cloned()
+        .unwrap_or_default();
+
+    let detail = SensorDetail {
+        iopin: onew.name.clone(),
+        family: format!("0x{:02X}", device.family_code()),
+        parasite: live_parasite.or(stats.parasite),
+        scratchpad,
+        scratchpad_error,
+        unit,
+        last_reading,
+        stats,
+        sensor,
+    };
+    (StatusCode::OK, Json(detail)).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnitQuery {
+    pub unit: Option<TempUnit>,
+}
+
 pub async fn get_temp(
     State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<UnitQuery>,
 ) -> (StatusCode, Json<TempValues>) {
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
     info!("#{cnt} get_temp()");
 
+    let unit = query.unit.unwrap_or(state.config.unit);
     let ret = {
         let data = state.data.read().await;
         // info!("My current data:\n{data:#?}");
@@ -167,24 +272,292 @@ pub async fn get_temp(
             last_update: data.last_update.clone(),
             uptime: data.uptime,
             uptime_s: data.uptime_s.clone(),
+            unit,
             temperatures: data
                 .temperatures
                 .iter()
                 // do not return invalid values
                 .filter(|v| v.value > NO_TEMP)
-                .cloned()
+                .map(|v| v.in_unit(unit))
                 .collect::<Vec<TempData>>(),
         }
     };
     (StatusCode::OK, Json(ret))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MeasureQuery {
+    pub pin: Option<String>,
+    pub sensor: Option<String>,
+    pub unit: Option<TempUnit>,
+}
+
+/// `POST /measure` response: `/temp` plus the requested sensors that failed
+#[derive(Clone, Debug, Serialize)]
+pub struct MeasureValues {
+    #[serde(flatten)]
+    pub values: TempValues,
+    pub failed: Vec<MeasureFailure>,
+}
+
+pub async fn post_measure(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<MeasureQuery>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_measure()");
+
+    let scope = match (query.pin, query.sensor) {
+        (None, None) => MeasureScope::All,
+        (Some(pin), None) => MeasureScope::Pin(pin),
+        (None, Some(sensor)) => MeasureScope::Sensor(sensor),
+        (Some(_), Some(_)) => {
+            let msg = "Give either pin or sensor, not both";
+            error!("{msg}");
+            return (StatusCode::BAD_REQUEST, msg.to_string()).into_response();
+        }
+    };
+
+    // no measurements in AP mode, and none before WiFi and NTP are up
+    if state.ap_mode || !*state.poller_ready.read().await {
+        let msg = "Sensor poller is not ready";
+        error!("{msg}");
+        return (StatusCode::SERVICE_UNAVAILABLE, msg.to_string()).into_response();
+    }
+
+    let (resp, rx) = tokio::sync::oneshot::channel();
+    if state
+        .measure_tx
+        .send(MeasureRequest { scope, resp })
+        .is_err()
+    {
+        let msg = "Sensor poller is not running";
+        error!("{msg}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, msg.to_string()).into_response();
+    }
+
+    let reply = match timeout(Duration::from_secs(MEASURE_TIMEOUT_S), rx).await {
+        Ok(Ok(Some(reply))) => reply,
+        Ok(Ok(None)) => {
+            let msg = "No such pin or sensor";
+            error!("{msg}");
+            return (StatusCode::NOT_FOUND, msg.to_string()).into_response();
+        }
+        Ok(Err(_)) | Err(_) => {
+            let msg = "Measurement timed out";
+            error!("{msg}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, msg.to_string()).into_response();
+        }
+    };
+
+    // an empty list would look like success to the caller
+    if reply.temperatures.is_empty() {
+        let failures = reply
+            .failed
+            .iter()
+            .map(|f| format!("{} on {}: {}", f.sensor, f.iopin, f.error))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let msg = format!("No sensor could be measured:\n{failures}");
+        error!("{msg}");
+        return (StatusCode::BAD_GATEWAY, msg).into_response();
+    }
+
+    let unit = query.unit.unwrap_or(state.config.unit);
+    let ret = {
+        let data = state.data.read().await;
+        MeasureValues {
+            values: TempValues {
+                timestamp: data.timestamp,
+                last_update: data.last_update.clone(),
+                uptime: data.uptime,
+                uptime_s: data.uptime_s.clone(),
+                unit,
+                temperatures: reply
+                    .temperatures
+                    .iter()
+                    .map(|v| v.in_unit(unit))
+                    .collect::<Vec<TempData>>(),
+            },
+            failed: reply.failed,
+        }
+    };
+    (StatusCode::OK, Json(ret)).into_response()
+}
+
+pub async fn get_degree_days(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<UnitQuery>,
+) -> (StatusCode, Json<DegreeDayTotals>) {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_degree_days()");
+
+    let unit = query.unit.unwrap_or(state.config.unit);
+    let degree_days = state.degree_days.read().await.in_unit(unit);
+    (StatusCode::OK, Json(degree_days))
+}
+
+pub async fn get_thermostat(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+) -> (StatusCode, Json<ThermostatStatus>) {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_thermostat()");
+
+    let status = state.thermostat.read().await.status(&state.config);
+    (StatusCode::OK, Json(status))
+}
+
+pub async fn post_thermostat(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Json(cmd): Json<ThermostatCommand>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_thermostat()");
+
+    match set_thermostat(&state, cmd).await {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => {
+            let msg = format!("Thermostat update failed: {e:#}");
+            error!("{msg}");
+            (StatusCode::BAD_REQUEST, msg).into_response()
+        }
+    }
+}
+
+pub async fn get_pwm(State(state): State<Arc<Pin<Box<MyState>>>>) -> (StatusCode, Json<PwmStatus>) {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_pwm()");
+
+    let status = state.pwm.read().await.status(&state.config);
+    (StatusCode::OK, Json(status))
+}
+
+pub async fn post_pwm(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Json(cmd): Json<PwmCommand>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_pwm()");
+
+    match set_pwm_control(&state, cmd).await {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => {
+            let msg = format!("PWM control update failed: {e:#}");
+            error!("{msg}");
+            (StatusCode::BAD_REQUEST, msg).into_response()
+        }
+    }
+}
+
+pub async fn get_alerts(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<UnitQuery>,
+) -> (StatusCode, Json<AlertValues>) {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_alerts()");
+
+    let unit = query.unit.unwrap_or(state.config.unit);
+    let alerts = state
+        .alerts
+        .read()
+        .await
+        .iter()
+        .map(|a| a.status(&state.config, unit))
+        .collect();
+    (StatusCode::OK, Json(AlertValues { alerts }))
+}
+
+pub async fn get_metrics(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_metrics()");
+
+    let metrics = render_metrics(&state).await;
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)],
+        metrics,
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    pub lines: Option<usize>,
+    // present as `?follow`, with or without a value
+    pub follow: Option<String>,
+}
+
+pub async fn get_log(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<LogQuery>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    // at debug level, a polling log viewer would otherwise fill the buffer with its own requests
+    debug!("#{cnt} get_log()");
+
+    let (text, next_seq) = log_lines(0, query.lines.unwrap_or(usize::MAX));
+    let headers = [
+        (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+        (header::CACHE_CONTROL, "no-cache"),
+    ];
+    if query.follow.is_none() {
+        return (StatusCode::OK, headers, text).into_response();
+    }
+
+    // the backlog first, then new lines as they are logged until the client goes away
+    let backlog = stream::once(async move { Ok::<_, Infallible>(text) });
+    let live = stream::unfold(next_seq, |since| async move {
+        loop {
+            sleep(Duration::from_millis(LOG_FOLLOW_POLL_MS)).await;
+            let (text, next_seq) = log_lines(since, usize::MAX);
+            if !text.is_empty() {
+                return Some((Ok(text), next_seq));
+            }
+        }
+    });
+    (
+        StatusCode::OK,
+        headers,
+        Body::from_stream(backlog.chain(live)),
+    )
+        .into_response()
+}
+
+pub async fn get_loglevel(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+) -> (StatusCode, Json<LogLevelStatus>) {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_loglevel()");
+
+    (StatusCode::OK, Json(log_level_status(&state).await))
+}
+
+pub async fn post_loglevel(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Json(cmd): Json<LogLevelCommand>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_loglevel()");
+
+    match set_log_level(&state, cmd).await {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => {
+            let msg = format!("Log level update failed: {e:#}");
+            error!("{msg}");
+            (StatusCode::BAD_REQUEST, msg).into_response()
+        }
+    }
+}
+
 pub async fn get_config(
     State(state): State<Arc<Pin<Box<MyState>>>>,
 ) -> (StatusCode, Json<MyConfig>) {
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
     info!("#{cnt} get_conf()");
-    (StatusCode::OK, Json(state.config.clone()))
+    (
+        StatusCode::OK,
+        Json(state.saved_config.read().await.clone()),
+    )
 }
 
 pub async fn post_config(
@@ -222,8 +595,7 @@ pub async fn reset_config(State(state): State<Arc<Pin<Box<MyState>>>>) -> (Statu
 }
 
 async fn save_conf(state: Arc<Pin<Box<MyState>>>, config: MyConfig) -> (StatusCode, String) {
-    let mut nvs = state.nvs.write().await;
-    match config.to_nvs(&mut nvs) {
+    match state.save_config(config).await {
         Ok(_) => {
             info!("Config saved to nvs. Resetting soon...");
             *state.reset.write().await = true;
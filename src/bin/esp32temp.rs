@@ -3,7 +3,15 @@
 #![warn(clippy::large_futures)]
 
 use esp_idf_svc::{
-    eventloop::EspSystemEventLoop, hal::gpio, ota::EspOta, ping, timer::EspTaskTimerService,
+    eventloop::EspSystemEventLoop,
+    hal::{
+        gpio,
+        ledc::{LedcDriver, LedcTimerDriver, Resolution, config::TimerConfig},
+        units::Hertz,
+    },
+    ota::EspOta,
+    ping,
+    timer::EspTaskTimerService,
 };
 use esp_idf_sys::esp;
 
@@ -19,7 +27,7 @@ const BUTTON_COUNTDOWN_STEP_MS: u64 = 500;
 
 fn main() -> anyhow::Result<()> {
     esp_idf_sys::link_patches();
-    esp_idf_svc::log::EspLogger::initialize_default();
+    init_logger();
 
     // eventfd is needed by our mio poll implementation.  Note you should set max_fds
     // higher if you have other code that may need eventfd.
@@ -89,6 +97,13 @@ fn main() -> anyhow::Result<()> {
         info!("No WiFi configuration stored, starting in AP mode.");
     }
     info!("My config:\n{config:#?}");
+    config.apply_timezone();
+    if let Err(e) = LogLevels::parse(&config.log_level, &config.log_modules)
+        .and_then(|levels| apply_log_levels(&levels))
+    {
+        error!("Log level config not applied: {e:#}");
+    }
+    let degree_days = DegreeDays::from_nvs(&mut nvs).unwrap_or_default();
 
     let peripherals = Peripherals::take().unwrap();
     let pins = peripherals.pins;
@@ -130,15 +145,57 @@ fn main() -> anyhow::Result<()> {
         (pins.gpio33.degrade_input_output(), "gpio33"),
     ]);
 
+    // The DS2482 bridge takes over two of the 1-Wire pins as its I2C bus
+    let backends = config.bus_backends();
+    let ds2482 = backends.ds2482.enabled();
+    let mut i2c_pins = (None, None);
+    // and the thermostat relay and PWM outputs take one each
+    let mut relay = None;
+    let mut pwm_pin = None;
+    let mut buses = Vec::with_capacity(hw_onewire_pins.len());
+    for (pin, name) in hw_onewire_pins.into_iter() {
+        if config.thermo_enable && config.thermo_pin.trim().eq_ignore_ascii_case(name) {
+            relay = Some(gpio::PinDriver::output(pin)?);
+        } else if config.pwm_enable && config.pwm_pin.trim().eq_ignore_ascii_case(name) {
+            pwm_pin = Some(pin);
+        } else if ds2482 && config.ds2482_sda.trim().eq_ignore_ascii_case(name) {
+            i2c_pins.0 = Some(pin);
+        } else if ds2482 && config.ds2482_scl.trim().eq_ignore_ascii_case(name) {
+            i2c_pins.1 = Some(pin);
+        } else {
+            let bitbang = backends.gpio(name) == GpioBackend::Bitbang;
+            buses.push((OWBus::gpio(pin, bitbang), name.to_string()));
+        }
+    }
+    if let (Some(sda), Some(scl)) = i2c_pins {
+        match Ds2482::new(peripherals.i2c0, sda, scl, config.ds2482_addr) {
+            Ok(bridge) => {
+                let bridge = Arc::new(bridge);
+                for ch in (0..bridge.channels()).filter(|ch| backends.ds2482.includes(*ch)) {
+                    buses.push((
+                        OWBus::Ds2482(Ds2482Channel::new(bridge.clone(), ch)),
+                        format!("ds2482-ch{ch}"),
+                    ));
+                }
+            }
+            Err(e) => error!("DS2482 init error: {e:#}"),
+        }
+    } else if ds2482 {
+        error!(
+            "DS2482 not started, SDA {:?} and SCL {:?} must be two free 1-Wire pins",
+            config.ds2482_sda, config.ds2482_scl
+        );
+    }
+
     info!("Scanning 1-wire devices...");
     let mut n_sensors = 0;
-    let mut onewire_pins = Vec::with_capacity(hw_onewire_pins.len());
-    for (i, (mut pin, name)) in hw_onewire_pins.into_iter().enumerate() {
-        let mut w = OWDriver::new(unsafe { pin.reborrow() })?;
-        match scan_1wire(&mut w) {
+    let mut found = Vec::with_capacity(buses.len());
+    for (i, (mut bus, name)) in buses.into_iter().enumerate() {
+        let mut w = bus.open()?;
+        let scan = scan_1wire(w.bus());
+        bus.close(w);
+        match scan {
             Ok(scan) => {
-                drop(w);
-
                 if scan.all_devices.is_empty() {
                     info!("Onewire response[{i}]: {name} no devices");
                 } else {
@@ -153,20 +210,32 @@ fn main() -> anyhow::Result<()> {
 
                 if !scan.ds18b20_devices.is_empty() {
                     n_sensors += scan.ds18b20_devices.len();
-                    onewire_pins.push(MyOnewire {
-                        pin,
-                        name: name.to_string(),
-                        ids: scan.ds18b20_devices,
-                    });
+                    found.push((bus, name, scan.ds18b20_devices));
                 }
             }
             Err(e) => {
-                drop(w);
                 error!("Onewire scan error[{i}] {name}: {e:#}");
             }
         }
     }
 
+    // from now on, buses are only driven by the 1-Wire worker thread
+    let (found_buses, found_sensors): (Vec<_>, Vec<_>) = found
+        .into_iter()
+        .map(|(bus, name, ids)| (bus, (name, ids)))
+        .unzip();
+    let onewire_pins = start_onewire_worker(found_buses)?
+        .into_iter()
+        .zip(found_sensors)
+        .map(|(client, (name, ids))| MyOnewire {
+            bus: Mutex::new(client),
+            name,
+            ids,
+        })
+        .collect::<Vec<_>>();
+    // outgoing HTTP requests (alert webhooks, InfluxDB, HTTP push) are blocking as well
+    let http = start_http_worker()?;
+
     // populate the temp_data structure
     let mut temp_data = TempValues::with_capacity(n_sensors);
     (0..n_sensors).for_each(|_| {
@@ -174,6 +243,13 @@ fn main() -> anyhow::Result<()> {
             iopin: "N/A".into(),
             sensor: "N/A".into(),
             value: NO_TEMP,
+            interval: 0,
+            rate_min: None,
+            rate_h: None,
+            trend: Trend::Unknown,
+            timestamp: 0,
+            retries: 0,
+            conversion_ms: 0,
         });
     });
 
@@ -183,6 +259,39 @@ fn main() -> anyhow::Result<()> {
         Some(nvs_default_partition),
     )?;
 
+    if config.thermo_enable && relay.is_none() {
+        error!(
+            "Thermostat relay pin {:?} is not available, relay disabled",
+            config.thermo_pin
+        );
+    }
+    let thermostat = Thermostat::new(&config, relay);
+
+    // a bad frequency/resolution combination only disables the output
+    let pwm_driver = pwm_pin.and_then(|pin| {
+        let driver = LedcTimerDriver::new(
+            peripherals.ledc.timer0,
+            &TimerConfig::default()
+                .frequency(Hertz(config.pwm_freq))
+                .resolution(Resolution::Bits10),
+        )
+        .and_then(|timer| LedcDriver::new(peripherals.ledc.channel0, timer, pin));
+        match driver {
+            Ok(driver) => Some(driver),
+            Err(e) => {
+                error!("PWM output init error: {e:#}");
+                None
+            }
+        }
+    });
+    if config.pwm_enable && pwm_driver.is_none() {
+        error!(
+            "PWM output pin {:?} is not available, output disabled",
+            config.pwm_pin
+        );
+    }
+    let pwm = PwmControl::new(&config, pwm_driver);
+
     let state = Box::pin(MyState::new(
         ap_mode,
         config,
@@ -190,6 +299,10 @@ fn main() -> anyhow::Result<()> {
         ota_slot,
         onewire_pins,
         temp_data,
+        degree_days,
+        thermostat,
+        pwm,
+        http,
         led,
     ));
     let shared_state = Arc::new(state);
@@ -209,10 +322,19 @@ fn main() -> anyhow::Result<()> {
                 _ = Box::pin(poll_reset(shared_state.clone(), button)) => { error!("poll_reset() ended."); }
                 _ = Box::pin(poll_sensors(shared_state.clone())) => { error!("poll_sensors() ended."); }
                 _ = Box::pin(run_mqtt(shared_state.clone())) => { error!("run_mqtt() ended."); }
+                _ = Box::pin(run_influx(shared_state.clone())) => { error!("run_influx() ended."); }
+                _ = Box::pin(run_http_push(shared_state.clone())) => { error!("run_http_push() ended."); }
+                _ = Box::pin(run_graphite(shared_state.clone())) => { error!("run_graphite() ended."); }
+                _ = Box::pin(run_announce(shared_state.clone())) => { error!("run_announce() ended."); }
+                _ = Box::pin(run_syslog(shared_state.clone())) => { error!("run_syslog() ended."); }
                 _ = Box::pin(run_api_server(shared_state.clone())) => { error!("run_api_server() ended."); }
                 _ = Box::pin(run_esphome_api(shared_state.clone())) => { error!("run_esphome_api() ended."); }
                 _ = Box::pin(wifi_loop.run(wifidriver, sysloop, timer)) => { error!("wifi_loop.run() ended."); }
                 _ = Box::pin(pinger(shared_state.clone())) => { error!("pinger() ended."); }
+                _ = Box::pin(run_thermostat(shared_state.clone())) => { error!("run_thermostat() ended."); }
+                _ = Box::pin(run_pwm_control(shared_state.clone())) => { error!("run_pwm_control() ended."); }
+                _ = Box::pin(run_alerts(shared_state.clone())) => { error!("run_alerts() ended."); }
+                _ = Box::pin(run_degree_days(shared_state.clone())) => { error!("run_degree_days() ended."); }
             };
         }));
 
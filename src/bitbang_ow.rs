@@ -0,0 +1,169 @@
+// bitbang_ow.rs
+//
+// Software (bit-banged) 1-Wire bus backend on a plain open-drain GPIO.
+// Time critical parts of each slot run with interrupts disabled. This does not
+// need an RMT channel, so it is used for buses configured with it and as a
+// fallback when `onewire_new_bus_rmt` fails.
+
+use std::cell::RefCell;
+
+use esp_idf_hal::interrupt;
+use esp_idf_sys::{ESP_ERR_NOT_FOUND, EspError};
+
+use crate::*;
+
+// Standard speed slot timings (us)
+const RESET_LOW_US: u32 = 480;
+const PRESENCE_SAMPLE_US: u32 = 70;
+const RESET_RECOVERY_US: u32 = 410;
+const WRITE_1_LOW_US: u32 = 6;
+const WRITE_1_RECOVERY_US: u32 = 64;
+const WRITE_0_LOW_US: u32 = 60;
+const WRITE_0_RECOVERY_US: u32 = 10;
+const READ_LOW_US: u32 = 6;
+const READ_SAMPLE_US: u32 = 9;
+const READ_RECOVERY_US: u32 = 55;
+
+pub struct BitBangOW<'a> {
+    pin: RefCell<PinDriver<'a, InputOutput>>,
+}
+
+impl<'a> BitBangOW<'a> {
+    pub fn new(pin: AnyIOPin<'a>) -> Result<Self, EspError> {
+        let mut pin = PinDriver::input_output_od(pin, Pull::Up)?;
+        pin.set_high()?;
+        Ok(Self {
+            pin: RefCell::new(pin),
+        })
+    }
+
+    /// Reset the pin and give it back
+    pub fn into_pin(self) -> AnyIOPin<'a> {
+        let gpio = self.pin.borrow().pin();
+        drop(self);
+        // SAFETY: the driver that owned the pin is gone, PinDriver has no way to return it
+        unsafe { AnyIOPin::steal(gpio) }
+    }
+}
+
+fn reset_pulse(pin: &mut PinDriver<'_, InputOutput>) -> Result<(), EspError> {
+    pin.set_low()?;
+    Ets::delay_us(RESET_LOW_US);
+    let presence = interrupt::free(|| -> Result<bool, EspError> {
+        pin.set_high()?;
+        Ets::delay_us(PRESENCE_SAMPLE_US);
+        Ok(pin.is_low())
+    })?;
+    Ets::delay_us(RESET_RECOVERY_US);
+
+    if presence {
+        Ok(())
+    } else {
+        Err(EspError::from_infallible::<ESP_ERR_NOT_FOUND>())
+    }
+}
+
+fn write_bit(pin: &mut PinDriver<'_, InputOutput>, bit: bool) -> Result<(), EspError> {
+    let (low_us, recovery_us) = if bit {
+        (WRITE_1_LOW_US, WRITE_1_RECOVERY_US)
+    } else {
+        (WRITE_0_LOW_US, WRITE_0_RECOVERY_US)
+    };
+    interrupt::free(|| -> Result<(), EspError> {
+        pin.set_low()?;
+        Ets::delay_us(low_us);
+        pin.set_high()
+    })?;
+    Ets::delay_us(recovery_us);
+    Ok(())
+}
+
+fn read_bit(pin: &mut PinDriver<'_, InputOutput>) -> Result<bool, EspError> {
+    let bit = interrupt::free(|| -> Result<bool, EspError> {
+        pin.set_low()?;
+        Ets::delay_us(READ_LOW_US);
+        pin.set_high()?;
+        Ets::delay_us(READ_SAMPLE_US);
+        Ok(pin.is_high())
+    })?;
+    Ets::delay_us(READ_RECOVERY_US);
+    Ok(bit)
+}
+
+fn write_byte(pin: &mut PinDriver<'_, InputOutput>, byte: u8) -> Result<(), EspError> {
+    for i in 0..8 {
+        write_bit(pin, byte & (1 << i) != 0)?;
+    }
+    Ok(())
+}
+
+fn read_byte(pin: &mut PinDriver<'_, InputOutput>) -> Result<u8, EspError> {
+    let mut byte = 0u8;
+    for i in 0..8 {
+        if read_bit(pin)? {
+            byte |= 1 << i;
+        }
+    }
+    Ok(byte)
+}
+
+impl TripletSearch for BitBangOW<'_> {
+    fn search_reset(&mut self) -> Result<(), EspError> {
+        reset_pulse(self.pin.get_mut())
+    }
+
+    fn search_write_byte(&mut self, byte: u8) -> Result<(), EspError> {
+        write_byte(self.pin.get_mut(), byte)
+    }
+
+    fn search_triplet(&mut self, direction: bool) -> Result<(bool, bool, bool), EspError> {
+        let pin = self.pin.get_mut();
+        let id_bit = read_bit(pin)?;
+        let cmp_id_bit = read_bit(pin)?;
+        if id_bit && cmp_id_bit {
+            return Ok((true, true, direction));
+        }
+
+        let direction = if id_bit != cmp_id_bit {
+            id_bit
+        } else {
+            direction
+        };
+        write_bit(pin, direction)?;
+        Ok((id_bit, cmp_id_bit, direction))
+    }
+}
+
+impl OneWireBus for BitBangOW<'_> {
+    fn reset(&self) -> Result<(), EspError> {
+        reset_pulse(&mut self.pin.borrow_mut())
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<(), EspError> {
+        let mut pin = self.pin.borrow_mut();
+        for b in buf.iter_mut() {
+            *b = read_byte(&mut pin)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), EspError> {
+        let mut pin = self.pin.borrow_mut();
+        for b in data.iter() {
+            write_byte(&mut pin, *b)?;
+        }
+        Ok(())
+    }
+
+    fn search_devices(&mut self) -> Result<Vec<OWAddress>, EspError> {
+        self.search_rom()
+    }
+
+    // Only the internal pull-up is available on a bare GPIO, so this is a no-op and
+    // parasite-powered probes are warned about when their power mode is read
+    fn strong_pullup(&self, _enable: bool) -> Result<(), EspError> {
+        Ok(())
+    }
+}
+
+// EOF
@@ -0,0 +1,150 @@
+// bus_backends.rs
+//
+// Per-bus backend selection from the `onewire_buses` config value, e.g.
+// "gpio6=bitbang, gpio7=rmt, ds2482-ch0, ds2482-ch3". GPIO pins without an entry
+// are RMT buses, and the DS2482 bridge is used only when it has an entry.
+
+use anyhow::bail;
+
+/// DS2482-800 has the most channels
+pub const DS2482_MAX_CHANNELS: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpioBackend {
+    Rmt,
+    Bitbang,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Ds2482Channels {
+    #[default]
+    Off,
+    // every channel the bridge has
+    All,
+    Only(Vec<u8>),
+}
+
+impl Ds2482Channels {
+    pub fn enabled(&self) -> bool {
+        *self != Ds2482Channels::Off
+    }
+
+    pub fn includes(&self, channel: u8) -> bool {
+        match self {
+            Ds2482Channels::Off => false,
+            Ds2482Channels::All => true,
+            Ds2482Channels::Only(channels) => channels.contains(&channel),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BusBackends {
+    // pin name and backend, for the pins that have an entry
+    gpio: Vec<(String, GpioBackend)>,
+    pub ds2482: Ds2482Channels,
+}
+
+impl BusBackends {
+    /// Parse comma-separated `gpioN=rmt`, `gpioN=bitbang`, `ds2482` (all channels)
+    /// and `ds2482-chN` entries
+    pub fn parse(buses: &str) -> anyhow::Result<Self> {
+        let mut backends = BusBackends::default();
+        for entry in buses.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let lower = entry.to_ascii_lowercase();
+            if lower == "ds2482" {
+                backends.ds2482 = Ds2482Channels::All;
+            } else if let Some(channel) = lower.strip_prefix("ds2482-ch") {
+                let Some(channel) = channel
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|c| *c < DS2482_MAX_CHANNELS)
+                else {
+                    bail!("invalid DS2482 channel in {entry:?}, expected ds2482-ch0..7");
+                };
+                match &mut backends.ds2482 {
+                    Ds2482Channels::All => {}
+                    Ds2482Channels::Only(channels) => channels.push(channel),
+                    off => *off = Ds2482Channels::Only(vec![channel]),
+                }
+            } else if let Some((pin, backend)) = lower.split_once('=') {
+                let backend = match backend.trim() {
+                    "rmt" => GpioBackend::Rmt,
+                    "bitbang" => GpioBackend::Bitbang,
+                    other => {
+                        bail!("unknown backend {other:?} in {entry:?}, expected rmt or bitbang")
+                    }
+                };
+                let pin = pin.trim();
+                if !pin.starts_with("gpio") || pin[4..].parse::<u8>().is_err() {
+                    bail!("invalid pin in {entry:?}, expected e.g. gpio4");
+                }
+                backends.gpio.retain(|(p, _)| p != pin);
+                backends.gpio.push((pin.to_string(), backend));
+            } else {
+                bail!("invalid bus {entry:?}, expected gpioN=rmt|bitbang, ds2482 or ds2482-chN");
+            }
+        }
+        Ok(backends)
+    }
+
+    /// Backend of the GPIO bus on `pin_name` (e.g. "gpio4"), RMT unless set otherwise
+    pub fn gpio(&self, pin_name: &str) -> GpioBackend {
+        self.gpio
+            .iter()
+            .find(|(p, _)| p.eq_ignore_ascii_case(pin_name))
+            .map_or(GpioBackend::Rmt, |(_, backend)| *backend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_is_all_rmt() {
+        let b = BusBackends::parse(" ").unwrap();
+        assert_eq!(b.gpio("gpio4"), GpioBackend::Rmt);
+        assert!(!b.ds2482.enabled());
+        assert!(!b.ds2482.includes(0));
+    }
+
+    #[test]
+    fn gpio_backends() {
+        let b = BusBackends::parse("gpio6=bitbang, GPIO7 = rmt,gpio8=bitbang,gpio8=rmt").unwrap();
+        assert_eq!(b.gpio("gpio6"), GpioBackend::Bitbang);
+        assert_eq!(b.gpio("gpio7"), GpioBackend::Rmt);
+        // the last entry for a pin wins
+        assert_eq!(b.gpio("gpio8"), GpioBackend::Rmt);
+        assert_eq!(b.gpio("gpio9"), GpioBackend::Rmt);
+    }
+
+    #[test]
+    fn ds2482_channels() {
+        let b = BusBackends::parse("ds2482-ch0, ds2482-ch3").unwrap();
+        assert_eq!(b.ds2482, Ds2482Channels::Only(vec![0, 3]));
+        assert!(b.ds2482.includes(3) && !b.ds2482.includes(1));
+
+        let b = BusBackends::parse("ds2482-ch2, DS2482").unwrap();
+        assert_eq!(b.ds2482, Ds2482Channels::All);
+        let b = BusBackends::parse("ds2482, ds2482-ch2").unwrap();
+        assert_eq!(b.ds2482, Ds2482Channels::All);
+    }
+
+    #[test]
+    fn invalid_entries() {
+        for buses in [
+            "gpio6=spi",
+            "gpio6",
+            "pin6=rmt",
+            "gpio=rmt",
+            "ds2482-ch8",
+            "ds2482-chx",
+            "gpio4=rmt,,ds2482-ch",
+        ] {
+            assert!(BusBackends::parse(buses).is_err(), "{buses:?} accepted");
+        }
+    }
+}
+
+// EOF
@@ -1,21 +1,88 @@
 // config.rs
 
-use crc::{CRC_32_ISCSI, Crc};
-
 use crate::*;
 
-pub const NVS_BUF_SIZE: usize = 256;
 pub const HTTP_API_PORT: u16 = 80;
 const DEFAULT_SENSOR_RETRIES: u32 = 5;
 const DEFAULT_POLL_DELAY: u64 = 60;
-
-const CONFIG_NAME: &str = "cfg";
+const DEFAULT_POLL_DELAY_FAST: u64 = 15;
+const DEFAULT_RATE_THRESHOLD: f32 = 0.5;
+const DEFAULT_TREND_SAMPLES: u32 = 5;
+const DEFAULT_TREND_BAND: f32 = 0.05;
+const DEFAULT_DD_BASE: f32 = 18.0;
+const DEFAULT_TZ: &str = "UTC0";
+const DEFAULT_THERMO_SETPOINT: f32 = 21.0;
+const DEFAULT_THERMO_HYSTERESIS: f32 = 1.0;
+const DEFAULT_THERMO_MIN_TIME: u64 = 60;
+const DEFAULT_PWM_FREQ: u32 = 25_000;
+const DEFAULT_PWM_KP: f32 = 10.0;
+const DEFAULT_PWM_KI: f32 = 0.1;
+const DEFAULT_ALERT_HYSTERESIS: f32 = 1.0;
+const DEFAULT_ALERT_HOLDOFF: u64 = 60;
+const DEFAULT_INFLUX_URL: &str = "http://influxdb.local:8086";
+const DEFAULT_INFLUX_DB: &str = "esp32temp";
+const DEFAULT_INFLUX_MEASUREMENT: &str = "temperature";
+const DEFAULT_GRAPHITE_PORT: u16 = 2003;
+const DEFAULT_STATSD_PORT: u16 = 8125;
+const DEFAULT_GRAPHITE_PREFIX: &str = "sensors.{myid}";
+const DEFAULT_ANNOUNCE_ADDR: &str = "239.255.32.32";
+const DEFAULT_ANNOUNCE_PORT: u16 = 3232;
+const DEFAULT_SYSLOG_PORT: u16 = 514;
+const DEFAULT_SYSLOG_LEVEL: &str = "info";
+const DEFAULT_SYSLOG_RATE: u32 = 20;
+const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_PUSH_BODY: &str = r#"{"device":"{device}","timestamp":{timestamp},"values":{json}}"#;
+// DS2482 I2C bridge pins (SDA, SCL), these are taken out of the 1-Wire pin list when it is used
+#[cfg(feature = "esp32-c3")]
+const DEFAULT_DS2482_SDA_SCL: (&str, &str) = ("gpio5", "gpio6");
+#[cfg(feature = "esp-wroom-32")]
+const DEFAULT_DS2482_SDA_SCL: (&str, &str) = ("gpio21", "gpio22");
 
 #[derive(Clone, Debug, Serialize, Deserialize, Template)]
 #[template(path = "index.html.ask", escape = "html")]
 pub struct MyConfig {
     pub retries: u32,
     pub delay: u64,
+    pub delay_fast: u64,
+    pub rate_threshold: f32,
+    pub trend_samples: u32,
+    pub trend_band: f32,
+    pub dd_sensor: String,
+    pub dd_heat_base: f32,
+    pub dd_cool_base: f32,
+    pub tz: String,
+    pub unit: TempUnit,
+    pub sensor_aliases: String,
+    pub thermo_enable: bool,
+    pub thermo_sensor: String,
+    pub thermo_pin: String,
+    pub thermo_active_low: bool,
+    pub thermo_mode: ThermoMode,
+    pub thermo_setpoint: f32,
+    pub thermo_hysteresis: f32,
+    pub thermo_min_on: u64,
+    pub thermo_min_off: u64,
+    pub thermo_failsafe_on: bool,
+    pub pwm_enable: bool,
+    pub pwm_sensor: String,
+    pub pwm_pin: String,
+    pub pwm_freq: u32,
+    pub pwm_setpoint: f32,
+    pub pwm_kp: f32,
+    pub pwm_ki: f32,
+    pub pwm_kd: f32,
+    pub pwm_min: f32,
+    pub pwm_max: f32,
+    pub pwm_reverse: bool,
+    pub pwm_failsafe: f32,
+    pub alert_rules: String,
+    pub alert_hysteresis: f32,
+    pub alert_holdoff: u64,
+    pub alert_webhook: String,
+    pub onewire_buses: String,
+    pub ds2482_addr: u8,
+    pub ds2482_sda: String,
+    pub ds2482_scl: String,
 
     pub wifi_ssid: String,
     pub wifi_pass: String,
@@ -33,6 +100,53 @@ pub struct MyConfig {
     pub mqtt_enable: bool,
     pub mqtt_url: String,
     pub mqtt_topic: String,
+    pub mqtt_retain: bool,
+    pub mqtt_discovery: bool,
+    pub mqtt_discovery_prefix: String,
+
+    pub influx_enable: bool,
+    pub influx_url: String,
+    pub influx_v2: bool,
+    pub influx_db: String,
+    pub influx_org: String,
+    pub influx_user: String,
+    pub influx_pass: String,
+    pub influx_token: String,
+    pub influx_measurement: String,
+    pub influx_tags: String,
+    pub influx_batch: u32,
+
+    pub push_enable: bool,
+    pub push_method: String,
+    pub push_url: String,
+    pub push_body: String,
+    pub push_content_type: String,
+    pub push_headers: String,
+    pub push_per_sensor: bool,
+    pub push_expect: u16,
+
+    pub graphite_enable: bool,
+    pub graphite_host: String,
+    pub graphite_port: u16,
+    pub statsd_enable: bool,
+    pub statsd_host: String,
+    pub statsd_port: u16,
+    pub graphite_prefix: String,
+
+    pub announce_enable: bool,
+    pub announce_addr: String,
+    pub announce_port: u16,
+    pub announce_ttl: u32,
+
+    pub syslog_enable: bool,
+    pub syslog_server: String,
+    pub syslog_port: u16,
+    pub syslog_tcp: bool,
+    pub syslog_level: String,
+    pub syslog_rate: u32,
+
+    pub log_level: String,
+    pub log_modules: String,
 }
 
 impl Default for MyConfig {
@@ -54,9 +168,96 @@ impl Default for MyConfig {
             mqtt_enable: false,
             mqtt_url: "mqtt://mqtt.local:1883".into(),
             mqtt_topic: "esp32temp".into(),
+            mqtt_retain: false,
+            mqtt_discovery: false,
+            mqtt_discovery_prefix: "homeassistant".into(),
+
+            influx_enable: false,
+            influx_url: DEFAULT_INFLUX_URL.into(),
+            influx_v2: false,
+            influx_db: DEFAULT_INFLUX_DB.into(),
+            influx_org: String::new(),
+            influx_user: String::new(),
+            influx_pass: String::new(),
+            influx_token: String::new(),
+            influx_measurement: DEFAULT_INFLUX_MEASUREMENT.into(),
+            influx_tags: String::new(),
+            influx_batch: 1,
+
+            push_enable: false,
+            push_method: "POST".into(),
+            push_url: String::new(),
+            push_body: DEFAULT_PUSH_BODY.into(),
+            push_content_type: "application/json".into(),
+            push_headers: String::new(),
+            push_per_sensor: false,
+            push_expect: 0,
+
+            graphite_enable: false,
+            graphite_host: String::new(),
+            graphite_port: DEFAULT_GRAPHITE_PORT,
+            statsd_enable: false,
+            statsd_host: String::new(),
+            statsd_port: DEFAULT_STATSD_PORT,
+            graphite_prefix: DEFAULT_GRAPHITE_PREFIX.into(),
+
+            announce_enable: false,
+            announce_addr: DEFAULT_ANNOUNCE_ADDR.into(),
+            announce_port: DEFAULT_ANNOUNCE_PORT,
+            announce_ttl: 1,
+
+            syslog_enable: false,
+            syslog_server: String::new(),
+            syslog_port: DEFAULT_SYSLOG_PORT,
+            syslog_tcp: false,
+            syslog_level: DEFAULT_SYSLOG_LEVEL.into(),
+            syslog_rate: DEFAULT_SYSLOG_RATE,
+
+            log_level: DEFAULT_LOG_LEVEL.into(),
+            log_modules: String::new(),
 
             retries: DEFAULT_SENSOR_RETRIES,
             delay: DEFAULT_POLL_DELAY,
+            delay_fast: DEFAULT_POLL_DELAY_FAST,
+            rate_threshold: DEFAULT_RATE_THRESHOLD,
+            trend_samples: DEFAULT_TREND_SAMPLES,
+            trend_band: DEFAULT_TREND_BAND,
+            dd_sensor: String::new(),
+            dd_heat_base: DEFAULT_DD_BASE,
+            dd_cool_base: DEFAULT_DD_BASE,
+            tz: DEFAULT_TZ.into(),
+            unit: TempUnit::Celsius,
+            sensor_aliases: String::new(),
+            thermo_enable: false,
+            thermo_sensor: String::new(),
+            thermo_pin: String::new(),
+            thermo_active_low: false,
+            thermo_mode: ThermoMode::Off,
+            thermo_setpoint: DEFAULT_THERMO_SETPOINT,
+            thermo_hysteresis: DEFAULT_THERMO_HYSTERESIS,
+            thermo_min_on: DEFAULT_THERMO_MIN_TIME,
+            thermo_min_off: DEFAULT_THERMO_MIN_TIME,
+            thermo_failsafe_on: false,
+            pwm_enable: false,
+            pwm_sensor: String::new(),
+            pwm_pin: String::new(),
+            pwm_freq: DEFAULT_PWM_FREQ,
+            pwm_setpoint: DEFAULT_THERMO_SETPOINT,
+            pwm_kp: DEFAULT_PWM_KP,
+            pwm_ki: DEFAULT_PWM_KI,
+            pwm_kd: 0.0,
+            pwm_min: 0.0,
+            pwm_max: 100.0,
+            pwm_reverse: false,
+            pwm_failsafe: 0.0,
+            alert_rules: String::new(),
+            alert_hysteresis: DEFAULT_ALERT_HYSTERESIS,
+            alert_holdoff: DEFAULT_ALERT_HOLDOFF,
+            alert_webhook: String::new(),
+            onewire_buses: String::new(),
+            ds2482_addr: DS2482_DEFAULT_ADDR,
+            ds2482_sda: DEFAULT_DS2482_SDA_SCL.0.to_string(),
+            ds2482_scl: DEFAULT_DS2482_SDA_SCL.1.to_string(),
         }
     }
 }
@@ -66,44 +267,82 @@ impl MyConfig {
         !self.wifi_ssid.trim().is_empty()
     }
 
+    /// Set the POSIX TZ string (e.g. "EET-2EEST,M3.5.0/3,M10.5.0/4") for local time
+    pub fn apply_timezone(&self) {
+        let tz = if self.tz.trim().is_empty() {
+            DEFAULT_TZ
+        } else {
+            self.tz.trim()
+        };
+        // SAFETY: called once at boot before any other thread is started
+        unsafe {
+            std::env::set_var("TZ", tz);
+            esp_idf_sys::tzset();
+        }
+    }
+
+    /// Backend of every bus from `onewire_buses` (e.g. "gpio6=bitbang, ds2482-ch0"),
+    /// all GPIO buses on RMT and no DS2482 if it is invalid
+    pub fn bus_backends(&self) -> BusBackends {
+        BusBackends::parse(&self.onewire_buses).unwrap_or_else(|e| {
+            error!("Invalid onewire_buses {:?}: {e:#}", self.onewire_buses);
+            BusBackends::default()
+        })
+    }
+
+    /// Friendly name from `sensor_aliases` (e.g. "28ff0102030405a6=freezer, 28ff0a0b0c0d0e0f=boiler")
+    pub fn sensor_alias(&self, sensor: &str) -> Option<&str> {
+        self.sensor_aliases.split(',').find_map(|entry| {
+            let (id, alias) = entry.split_once('=')?;
+            let alias = alias.trim();
+            (id.trim().eq_ignore_ascii_case(sensor) && !alias.is_empty()).then_some(alias)
+        })
+    }
+
     pub fn from_nvs(nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> Option<Self> {
-        let mut nvsbuf = [0u8; NVS_BUF_SIZE];
-        info!("Reading up to {sz} bytes from nvs...", sz = NVS_BUF_SIZE);
-        let b = match nvs.get_blob(CONFIG_NAME, &mut nvsbuf) {
+        if let Some(b) = read_blob(nvs, CONFIG_NVS_KEY) {
+            info!("Got {sz} bytes from nvs. Parsing config...", sz = b.len());
+            return match decode_config(&b) {
+                Ok(c) => {
+                    info!("Successfully parsed config from nvs.");
+                    Some(c)
+                }
+                Err(e) => {
+                    error!("Cannot parse config from nvs: {e:#}");
+                    None
+                }
+            };
+        }
+
+        // settings of earlier firmware, migrated to the current format once
+        let b = read_blob(nvs, CONFIG_V1_NVS_KEY)?;
+        info!(
+            "Got {sz} bytes of old config from nvs. Migrating...",
+            sz = b.len()
+        );
+        let c: Self = match decode_config_v1(&b) {
+            Ok(c) => c,
             Err(e) => {
-                error!("Nvs read error {e:?}");
-                return None;
-            }
-            Ok(Some(b)) => b,
-            _ => {
-                error!("Nvs key not found");
+                error!("Cannot parse old config from nvs: {e:#}");
                 return None;
             }
         };
-        info!("Got {sz} bytes from nvs. Parsing config...", sz = b.len());
-
-        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
-        let digest = crc.digest();
-        match postcard::from_bytes_crc32::<MyConfig>(b, digest) {
-            Ok(c) => {
-                info!("Successfully parsed config from nvs.");
-                Some(c)
-            }
-            Err(e) => {
-                error!("Cannot parse config from nvs: {e:?}");
-                None
+        match c.to_nvs(nvs) {
+            Ok(_) => {
+                if let Err(e) = nvs.remove(CONFIG_V1_NVS_KEY) {
+                    error!("Cannot remove old config from nvs: {e:?}");
+                }
             }
+            Err(e) => error!("Cannot save migrated config: {e:#}"),
         }
+        Some(c)
     }
 
     pub fn to_nvs(&self, nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> anyhow::Result<()> {
-        let mut nvsbuf = [0u8; NVS_BUF_SIZE];
-        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
-        let digest = crc.digest();
-        let nvsdata = match postcard::to_slice_crc32(self, &mut nvsbuf, digest) {
+        let nvsdata = match encode_config(self) {
             Ok(d) => d,
             Err(e) => {
-                let estr = format!("Cannot encode config to buffer {e:?}");
+                let estr = format!("Cannot encode config {e:#}");
                 bail!("{estr}");
             }
         };
@@ -112,7 +351,7 @@ impl MyConfig {
             sz = nvsdata.len()
         );
 
-        match nvs.set_blob(CONFIG_NAME, nvsdata) {
+        match nvs.set_blob(CONFIG_NVS_KEY, &nvsdata) {
             Ok(_) => {
                 info!("Config saved.");
                 Ok(())
@@ -125,4 +364,31 @@ impl MyConfig {
     }
 }
 
+fn read_blob(nvs: &mut nvs::EspNvs<nvs::NvsDefault>, key: &str) -> Option<Vec<u8>> {
+    let len = match nvs.blob_len(key) {
+        Err(e) => {
+            error!("Nvs read error {e:?}");
+            return None;
+        }
+        Ok(Some(len)) => len,
+        Ok(None) => {
+            info!("Nvs key {key} not found");
+            return None;
+        }
+    };
+    let mut nvsbuf = vec![0u8; len];
+    match nvs.get_blob(key, &mut nvsbuf) {
+        Ok(Some(b)) => {
+            let len = b.len();
+            nvsbuf.truncate(len);
+            Some(nvsbuf)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Nvs read error {e:?}");
+            None
+        }
+    }
+}
+
 // EOF
@@ -0,0 +1,224 @@
+// config_store.rs
+//
+// Encoding of the configuration blob in NVS, kept free of ESP-IDF so the migration
+// from older firmware is unit tested on the host.
+//
+// The settings are stored as JSON with a format version, so settings added by a newer
+// firmware are filled in from their defaults instead of making the whole blob
+// unreadable. Earlier firmware stored a postcard encoding of `ConfigV1` under
+// another key, which is migrated the same way.
+
+use anyhow::bail;
+use crc::{CRC_32_ISCSI, Crc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+pub const CONFIG_VERSION: u32 = 2;
+pub const CONFIG_NVS_KEY: &str = "config";
+/// Key of the postcard `ConfigV1` blob of earlier firmware
+pub const CONFIG_V1_NVS_KEY: &str = "cfg";
+
+static CONFIG_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+#[derive(Serialize, Deserialize)]
+struct StoredConfig {
+    version: u32,
+    json: String,
+}
+
+/// The settings of earlier firmware, in the order they were encoded
+#[derive(Serialize, Deserialize)]
+struct ConfigV1 {
+    retries: u32,
+    delay: u64,
+
+    wifi_ssid: String,
+    wifi_pass: String,
+    wifi_wpa2ent: bool,
+    wifi_username: String,
+
+    v4dhcp: bool,
+    v4addr: std::net::Ipv4Addr,
+    v4mask: u8,
+    v4gw: std::net::Ipv4Addr,
+    dns1: std::net::Ipv4Addr,
+    dns2: std::net::Ipv4Addr,
+
+    esphome_enable: bool,
+    mqtt_enable: bool,
+    mqtt_url: String,
+    mqtt_topic: String,
+}
+
+pub fn encode_config<T: Serialize>(config: &T) -> anyhow::Result<Vec<u8>> {
+    let stored = StoredConfig {
+        version: CONFIG_VERSION,
+        json: serde_json::to_string(config)?,
+    };
+    Ok(postcard::to_allocvec_crc32(&stored, CONFIG_CRC.digest())?)
+}
+
+/// Decode a blob stored under `CONFIG_NVS_KEY`
+pub fn decode_config<T: Serialize + DeserializeOwned + Default>(blob: &[u8]) -> anyhow::Result<T> {
+    let stored: StoredConfig = postcard::from_bytes_crc32(blob, CONFIG_CRC.digest())?;
+    if stored.version > CONFIG_VERSION {
+        // written by a newer firmware, settings unknown here are left out
+        ::log::warn!(
+            "Config version {} is newer than {CONFIG_VERSION}",
+            stored.version
+        );
+    }
+    with_defaults(serde_json::from_str(&stored.json)?)
+}
+
+/// Decode a blob stored under `CONFIG_V1_NVS_KEY` by earlier firmware
+pub fn decode_config_v1<T: Serialize + DeserializeOwned + Default>(
+    blob: &[u8],
+) -> anyhow::Result<T> {
+    let old: ConfigV1 = postcard::from_bytes_crc32(blob, CONFIG_CRC.digest())?;
+    with_defaults(serde_json::to_value(old)?)
+}
+
+// Settings missing from the stored ones keep their default value
+fn with_defaults<T: Serialize + DeserializeOwned + Default>(
+    stored: serde_json::Value,
+) -> anyhow::Result<T> {
+    let mut config = serde_json::to_value(T::default())?;
+    let (Some(config_map), serde_json::Value::Object(stored)) = (config.as_object_mut(), stored)
+    else {
+        bail!("config is not a JSON object");
+    };
+    config_map.extend(stored);
+    Ok(serde_json::from_value(config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    // Settings of the baseline firmware and some added later, as in `MyConfig`
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestConfig {
+        retries: u32,
+        delay: u64,
+        delay_fast: u64,
+        wifi_ssid: String,
+        wifi_pass: String,
+        wifi_wpa2ent: bool,
+        wifi_username: String,
+        v4dhcp: bool,
+        v4addr: Ipv4Addr,
+        v4mask: u8,
+        v4gw: Ipv4Addr,
+        dns1: Ipv4Addr,
+        dns2: Ipv4Addr,
+        esphome_enable: bool,
+        mqtt_enable: bool,
+        mqtt_url: String,
+        mqtt_topic: String,
+        tz: String,
+        rate_threshold: f32,
+    }
+
+    impl Default for TestConfig {
+        fn default() -> Self {
+            Self {
+                retries: 5,
+                delay: 60,
+                delay_fast: 15,
+                wifi_ssid: String::new(),
+                wifi_pass: String::new(),
+                wifi_wpa2ent: false,
+                wifi_username: String::new(),
+                v4dhcp: true,
+                v4addr: Ipv4Addr::UNSPECIFIED,
+                v4mask: 0,
+                v4gw: Ipv4Addr::UNSPECIFIED,
+                dns1: Ipv4Addr::UNSPECIFIED,
+                dns2: Ipv4Addr::UNSPECIFIED,
+                esphome_enable: false,
+                mqtt_enable: false,
+                mqtt_url: "mqtt://mqtt.local:1883".into(),
+                mqtt_topic: "esp32temp".into(),
+                tz: "UTC0".into(),
+                rate_threshold: 0.5,
+            }
+        }
+    }
+
+    // Written by the earlier firmware: retries 3, delay 30, SSID "home", password
+    // "secret", static 192.168.1.50/24 via 192.168.1.1, DNS 1.1.1.1 and 8.8.8.8,
+    // ESPHome on, MQTT on at mqtt://broker:1883 with topic "temps"
+    const BASELINE_BLOB: &[u8] = &[
+        0x03, 0x1e, 0x04, 0x68, 0x6f, 0x6d, 0x65, 0x06, 0x73, 0x65, 0x63, 0x72, 0x65, 0x74, 0x00,
+        0x00, 0x00, 0xc0, 0xa8, 0x01, 0x32, 0x18, 0xc0, 0xa8, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x08, 0x08, 0x08, 0x08, 0x01, 0x01, 0x12, 0x6d, 0x71, 0x74, 0x74, 0x3a, 0x2f, 0x2f, 0x62,
+        0x72, 0x6f, 0x6b, 0x65, 0x72, 0x3a, 0x31, 0x38, 0x38, 0x33, 0x05, 0x74, 0x65, 0x6d, 0x70,
+        0x73, 0x15, 0x5e, 0x8a, 0xf9,
+    ];
+
+    #[test]
+    fn migrates_baseline_blob() {
+        let config: TestConfig = decode_config_v1(BASELINE_BLOB).unwrap();
+        assert_eq!(
+            config,
+            TestConfig {
+                retries: 3,
+                delay: 30,
+                wifi_ssid: "home".into(),
+                wifi_pass: "secret".into(),
+                v4dhcp: false,
+                v4addr: Ipv4Addr::new(192, 168, 1, 50),
+                v4mask: 24,
+                v4gw: Ipv4Addr::new(192, 168, 1, 1),
+                dns1: Ipv4Addr::new(1, 1, 1, 1),
+                dns2: Ipv4Addr::new(8, 8, 8, 8),
+                esphome_enable: true,
+                mqtt_enable: true,
+                mqtt_url: "mqtt://broker:1883".into(),
+                mqtt_topic: "temps".into(),
+                ..TestConfig::default()
+            }
+        );
+
+        // a damaged blob is not taken for a config
+        let mut blob = BASELINE_BLOB.to_vec();
+        blob[4] ^= 1;
+        assert!(decode_config_v1::<TestConfig>(&blob).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = TestConfig {
+            wifi_ssid: "home".into(),
+            tz: "EET-2EEST,M3.5.0/3,M10.5.0/4".into(),
+            rate_threshold: 0.25,
+            ..TestConfig::default()
+        };
+        let blob = encode_config(&config).unwrap();
+        assert_eq!(decode_config::<TestConfig>(&blob).unwrap(), config);
+        // not mistaken for the old format
+        assert!(decode_config_v1::<TestConfig>(&blob).is_err());
+    }
+
+    #[test]
+    fn added_settings_get_defaults() {
+        let stored = StoredConfig {
+            version: CONFIG_VERSION,
+            json: r#"{"retries":7,"wifi_ssid":"home","removed_setting":1}"#.into(),
+        };
+        let blob = postcard::to_allocvec_crc32(&stored, CONFIG_CRC.digest()).unwrap();
+        let config: TestConfig = decode_config(&blob).unwrap();
+        assert_eq!(
+            config,
+            TestConfig {
+                retries: 7,
+                wifi_ssid: "home".into(),
+                ..TestConfig::default()
+            }
+        );
+    }
+}
+
+// EOF
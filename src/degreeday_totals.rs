@@ -0,0 +1,240 @@
+// degreeday_totals.rs
+//
+// Degree-day accumulation, kept free of ESP-IDF so it is unit tested on the host.
+// Readings integrate the difference to the base temperature over the time since the
+// previous one, and the daily and monthly totals roll over at local midnight.
+
+use chrono::{DateTime, Datelike, TimeZone};
+use serde::{Deserialize, Serialize};
+
+pub const DD_SAVE_INTERVAL_S: i64 = 900;
+// longer gaps (sensor failing, reboot) are not integrated
+pub const DD_MAX_GAP_S: i64 = 3600;
+
+/// Accumulator state, persisted in NVS as it is
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DegreeDays {
+    // days since CE of `today`, months since year 0 of `month`
+    pub day: i32,
+    pub month: i32,
+    pub heat_today: f32,
+    pub cool_today: f32,
+    pub heat_yesterday: f32,
+    pub cool_yesterday: f32,
+    pub heat_month: f32,
+    pub cool_month: f32,
+
+    #[serde(skip)]
+    last_sample: Option<i64>,
+    #[serde(skip)]
+    last_save: i64,
+}
+
+/// Totals as reported by `/degreedays`, MQTT and ESPHome, without the rollover bookkeeping
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DegreeDayTotals {
+    pub heat_today: f32,
+    pub cool_today: f32,
+    pub heat_yesterday: f32,
+    pub cool_yesterday: f32,
+    pub heat_month: f32,
+    pub cool_month: f32,
+}
+
+impl DegreeDays {
+    pub fn totals(&self) -> DegreeDayTotals {
+        DegreeDayTotals {
+            heat_today: self.heat_today,
+            cool_today: self.cool_today,
+            heat_yesterday: self.heat_yesterday,
+            cool_yesterday: self.cool_yesterday,
+            heat_month: self.heat_month,
+            cool_month: self.cool_month,
+        }
+    }
+
+    /// Start a new day and month when `now` is past them, also without a reading.
+    /// Returns true when something rolled over.
+    pub fn roll_over<Tz: TimeZone>(&mut self, now: &DateTime<Tz>) -> bool {
+        let mut rolled = false;
+        let day = now.date_naive().num_days_from_ce();
+        let month = now.year() * 12 + now.month0() as i32;
+
+        if day != self.day {
+            // a day without data leaves yesterday at zero
+            (self.heat_yesterday, self.cool_yesterday) = if day == self.day + 1 {
+                (self.heat_today, self.cool_today)
+            } else {
+                (0.0, 0.0)
+            };
+            self.heat_today = 0.0;
+            self.cool_today = 0.0;
+            self.day = day;
+            rolled = true;
+        }
+        if month != self.month {
+            self.heat_month = 0.0;
+            self.cool_month = 0.0;
+            self.month = month;
+            rolled = true;
+        }
+        rolled
+    }
+
+    /// Add a reading taken at `now`. Returns true when the totals should be saved to NVS.
+    pub fn accumulate<Tz: TimeZone>(
+        &mut self,
+        now: &DateTime<Tz>,
+        temp: f32,
+        heat_base: f32,
+        cool_base: f32,
+    ) -> bool {
+        let mut save = self.roll_over(now);
+
+        let ts = now.timestamp();
+        if let Some(last) = self.last_sample {
+            let dt = ts - last;
+            if dt > 0 && dt <= DD_MAX_GAP_S {
+                let days = dt as f32 / 86400.0;
+                let heat = (heat_base - temp).max(0.0) * days;
+                let cool = (temp - cool_base).max(0.0) * days;
+                self.heat_today += heat;
+                self.cool_today += cool;
+                self.heat_month += heat;
+                self.cool_month += cool;
+            }
+        }
+        self.last_sample = Some(ts);
+
+        if ts - self.last_save >= DD_SAVE_INTERVAL_S {
+            save = true;
+        }
+        if save {
+            self.last_save = ts;
+        }
+        save
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, FixedOffset};
+
+    use super::*;
+
+    const HEAT_BASE: f32 = 15.0;
+    const COOL_BASE: f32 = 22.0;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(3600)
+            .unwrap()
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+    }
+
+    // one reading every 10 minutes from `start` for `hours` at a constant temperature
+    fn feed(dd: &mut DegreeDays, start: DateTime<FixedOffset>, hours: i64, temp: f32) {
+        for i in 0..=hours * 6 {
+            dd.accumulate(
+                &(start + Duration::minutes(i * 10)),
+                temp,
+                HEAT_BASE,
+                COOL_BASE,
+            );
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn integrates_heating_and_cooling() {
+        let mut dd = DegreeDays::default();
+        // 12 h at 5 °C is 10 K for half a day
+        feed(&mut dd, at(2026, 1, 10, 0, 0), 12, 5.0);
+        assert!(close(dd.heat_today, 5.0), "{}", dd.heat_today);
+        assert!(close(dd.heat_month, 5.0));
+        assert_eq!(dd.cool_today, 0.0);
+
+        // 6 h at 26 °C is 4 K for a quarter day, between the bases nothing accrues
+        let mut dd = DegreeDays::default();
+        feed(&mut dd, at(2026, 7, 10, 0, 0), 6, 26.0);
+        feed(&mut dd, at(2026, 7, 10, 12, 0), 6, 18.0);
+        assert!(close(dd.cool_today, 1.0), "{}", dd.cool_today);
+        assert_eq!(dd.heat_today, 0.0);
+    }
+
+    #[test]
+    fn skips_long_gaps() {
+        let mut dd = DegreeDays::default();
+        dd.accumulate(&at(2026, 1, 10, 0, 0), 5.0, HEAT_BASE, COOL_BASE);
+        dd.accumulate(&at(2026, 1, 10, 2, 0), 5.0, HEAT_BASE, COOL_BASE);
+        assert_eq!(dd.heat_today, 0.0);
+        // the late reading starts the next interval
+        dd.accumulate(&at(2026, 1, 10, 2, 36), 5.0, HEAT_BASE, COOL_BASE);
+        assert!(close(dd.heat_today, 0.25), "{}", dd.heat_today);
+    }
+
+    #[test]
+    fn day_rolls_over_into_yesterday() {
+        let mut dd = DegreeDays::default();
+        feed(&mut dd, at(2026, 1, 10, 12, 0), 6, 5.0);
+        assert!(dd.accumulate(&at(2026, 1, 11, 0, 5), 5.0, HEAT_BASE, COOL_BASE));
+        assert!(close(dd.heat_yesterday, 2.5), "{}", dd.heat_yesterday);
+        assert_eq!(dd.heat_today, 0.0);
+        assert!(close(dd.heat_month, 2.5));
+    }
+
+    #[test]
+    fn roll_over_without_readings() {
+        let mut dd = DegreeDays::default();
+        feed(&mut dd, at(2026, 1, 31, 12, 0), 6, 5.0);
+        assert!(!dd.roll_over(&at(2026, 1, 31, 23, 59)));
+
+        // the timer tick after midnight starts the day and the month
+        assert!(dd.roll_over(&at(2026, 2, 1, 0, 1)));
+        assert!(close(dd.heat_yesterday, 2.5));
+        assert_eq!(dd.heat_today, 0.0);
+        assert_eq!(dd.heat_month, 0.0);
+        assert!(!dd.roll_over(&at(2026, 2, 1, 0, 2)));
+
+        // a whole day without data leaves yesterday at zero
+        assert!(dd.roll_over(&at(2026, 2, 3, 0, 1)));
+        assert_eq!(dd.heat_yesterday, 0.0);
+    }
+
+    #[test]
+    fn saves_on_rollover_and_interval() {
+        let mut dd = DegreeDays::default();
+        assert!(dd.accumulate(&at(2026, 1, 10, 8, 0), 5.0, HEAT_BASE, COOL_BASE));
+        assert!(!dd.accumulate(&at(2026, 1, 10, 8, 10), 5.0, HEAT_BASE, COOL_BASE));
+        assert!(dd.accumulate(&at(2026, 1, 10, 8, 15), 5.0, HEAT_BASE, COOL_BASE));
+    }
+
+    #[test]
+    fn totals_leave_out_the_bookkeeping() {
+        let mut dd = DegreeDays::default();
+        feed(&mut dd, at(2026, 1, 10, 0, 0), 1, 5.0);
+        let json = serde_json::to_value(dd.totals()).unwrap();
+        let keys = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "cool_month",
+                "cool_today",
+                "cool_yesterday",
+                "heat_month",
+                "heat_today",
+                "heat_yesterday"
+            ]
+        );
+    }
+}
+
+// EOF
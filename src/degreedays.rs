@@ -0,0 +1,97 @@
+// degreedays.rs
+//
+// Heating and cooling degree-days for one configured sensor (typically an outdoor probe).
+// The accumulation is in `degreeday_totals.rs`, this feeds it the sensor's readings,
+// rolls the totals over at local midnight and persists them in NVS.
+
+use crc::{CRC_32_ISCSI, Crc};
+
+use crate::*;
+
+const DD_NVS_NAME: &str = "dd";
+const DD_NVS_BUF_SIZE: usize = 64;
+const DD_TICK_S: u64 = 60;
+
+impl DegreeDays {
+    pub fn from_nvs(nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> Option<Self> {
+        let mut nvsbuf = [0u8; DD_NVS_BUF_SIZE];
+        let b = match nvs.get_blob(DD_NVS_NAME, &mut nvsbuf) {
+            Ok(Some(b)) => b,
+            Ok(None) => return None,
+            Err(e) => {
+                error!("Nvs degree-day read error {e:?}");
+                return None;
+            }
+        };
+
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let digest = crc.digest();
+        match postcard::from_bytes_crc32::<DegreeDays>(b, digest) {
+            Ok(dd) => Some(dd),
+            Err(e) => {
+                error!("Cannot parse degree-days from nvs: {e:?}");
+                None
+            }
+        }
+    }
+
+    pub fn to_nvs(&self, nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> anyhow::Result<()> {
+        let mut nvsbuf = [0u8; DD_NVS_BUF_SIZE];
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let digest = crc.digest();
+        let nvsdata = postcard::to_slice_crc32(self, &mut nvsbuf, digest)?;
+        nvs.set_blob(DD_NVS_NAME, nvsdata)?;
+        Ok(())
+    }
+}
+
+/// Feed the configured degree-day sensor's reading into the accumulators.
+pub async fn update_degree_days(state: &MyState, sensor: &str, temp: f32) {
+    if state.config.dd_sensor.is_empty() || !state.config.dd_sensor.eq_ignore_ascii_case(sensor) {
+        return;
+    }
+
+    let mut dd = state.degree_days.write().await;
+    let save = dd.accumulate(
+        &Local::now(),
+        temp,
+        state.config.dd_heat_base,
+        state.config.dd_cool_base,
+    );
+    if save {
+        save_degree_days(state, &dd).await;
+    }
+}
+
+async fn save_degree_days(state: &MyState, dd: &DegreeDays) {
+    let mut nvs = state.nvs.write().await;
+    if let Err(e) = dd.to_nvs(&mut nvs) {
+        error!("Cannot save degree-days to nvs: {e:#}");
+    }
+}
+
+/// Roll the totals over at midnight also when the sensor has no fresh readings.
+pub async fn run_degree_days(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if state.config.dd_sensor.is_empty() {
+        info!("Degree-days are disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    loop {
+        sleep(Duration::from_secs(DD_TICK_S)).await;
+        // the clock is meaningless before NTP, and would reset the totals from NVS
+        if !*state.ntp_ok.read().await {
+            continue;
+        }
+
+        let mut dd = state.degree_days.write().await;
+        if dd.roll_over(&Local::now()) {
+            info!("Degree-days rolled over.");
+            save_degree_days(&state, &dd).await;
+        }
+    }
+}
+
+// EOF
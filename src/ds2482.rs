@@ -0,0 +1,300 @@
+// ds2482.rs
+//
+// 1-Wire bus backend for the DS2482-100 (1 channel) and DS2482-800 (8 channels)
+// I2C-to-1-Wire bridges. The bridge generates the 1-Wire timing itself and has
+// an active pull-up, which copes with long star topologies much better than
+// the RMT-driven bus. Every channel is exposed as a separate bus.
+
+use std::sync::Mutex;
+
+use esp_idf_hal::{
+    delay::TickType,
+    i2c::{I2cConfig, I2cDriver},
+    units::Hertz,
+};
+use esp_idf_sys::{ESP_ERR_NOT_FOUND, ESP_ERR_TIMEOUT, EspError, TickType_t};
+
+use crate::*;
+
+pub const DS2482_DEFAULT_ADDR: u8 = 0x18;
+const DS2482_I2C_FREQ: u32 = 100_000;
+const DS2482_I2C_TIMEOUT: TickType_t = TickType::new_millis(20).ticks();
+const DS2482_BUSY_POLLS: u32 = 100;
+
+// Bridge commands
+const CMD_DEVICE_RESET: u8 = 0xF0;
+const CMD_SET_READ_PTR: u8 = 0xE1;
+const CMD_WRITE_CONFIG: u8 = 0xD2;
+const CMD_CHANNEL_SELECT: u8 = 0xC3;
+const CMD_1WIRE_RESET: u8 = 0xB4;
+const CMD_1WIRE_WRITE_BYTE: u8 = 0xA5;
+const CMD_1WIRE_READ_BYTE: u8 = 0x96;
+const CMD_1WIRE_TRIPLET: u8 = 0x78;
+
+// Read pointer codes
+const REG_STATUS: u8 = 0xF0;
+const REG_DATA: u8 = 0xE1;
+
+// Status register bits
+const STATUS_1WB: u8 = 0x01;
+const STATUS_PPD: u8 = 0x02;
+const STATUS_RST: u8 = 0x10;
+const STATUS_SBR: u8 = 0x20;
+const STATUS_TSB: u8 = 0x40;
+const STATUS_DIR: u8 = 0x80;
+
+// Configuration register bits
+const CONFIG_APU: u8 = 0x01;
+const CONFIG_SPU: u8 = 0x04;
+
+// Channel select codes (written) and the matching read-back values, DS2482-800 only
+const CHANNEL_CODES: [(u8, u8); 8] = [
+    (0xF0, 0xB8),
+    (0xE1, 0xB1),
+    (0xD2, 0xAA),
+    (0xC3, 0xA3),
+    (0xB4, 0x9C),
+    (0xA5, 0x95),
+    (0x96, 0x8E),
+    (0x87, 0x87),
+];
+
+struct Ds2482Inner {
+    i2c: I2cDriver<'static>,
+    addr: u8,
+    channel: u8,
+    spu_armed: bool,
+}
+
+pub struct Ds2482 {
+    inner: Mutex<Ds2482Inner>,
+    channels: u8,
+}
+
+impl Ds2482 {
+    pub fn new(
+        i2c: impl esp_idf_hal::i2c::I2c + 'static,
+        sda: AnyIOPin<'static>,
+        scl: AnyIOPin<'static>,
+        addr: u8,
+    ) -> anyhow::Result<Self> {
+        let config = I2cConfig::new()
+            .baudrate(Hertz(DS2482_I2C_FREQ))
+            .sda_enable_pullup(true)
+            .scl_enable_pullup(true);
+        let i2c = I2cDriver::new(i2c, sda, scl, &config)?;
+
+        let mut inner = Ds2482Inner {
+            i2c,
+            addr,
+            channel: 0,
+            spu_armed: false,
+        };
+
+        inner
+            .i2c
+            .write(addr, &[CMD_DEVICE_RESET], DS2482_I2C_TIMEOUT)?;
+        let status = inner.read_byte()?;
+        if status & STATUS_RST == 0 {
+            bail!(
+                "DS2482 at 0x{addr:02X} did not acknowledge device reset (status 0x{status:02X})"
+            );
+        }
+        inner.write_config(CONFIG_APU)?;
+
+        // The DS2482-100 does not know the channel select command and NAKs it
+        let channels = match inner.select_channel(1) {
+            Ok(_) => {
+                inner.select_channel(0)?;
+                CHANNEL_CODES.len() as u8
+            }
+            Err(_) => 1,
+        };
+        info!("DS2482 at 0x{addr:02X}: {channels} channel(s)");
+
+        Ok(Self {
+            inner: Mutex::new(inner),
+            channels,
+        })
+    }
+
+    pub fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    fn with_channel<R>(
+        &self,
+        channel: u8,
+        f: impl FnOnce(&mut Ds2482Inner) -> Result<R, EspError>,
+    ) -> Result<R, EspError> {
+        let mut inner = self.inner.lock().unwrap();
+        if self.channels > 1 && inner.channel != channel {
+            inner.select_channel(channel)?;
+        }
+        f(&mut inner)
+    }
+}
+
+impl Ds2482Inner {
+    fn read_byte(&mut self) -> Result<u8, EspError> {
+        let mut buf = [0u8; 1];
+        self.i2c.read(self.addr, &mut buf, DS2482_I2C_TIMEOUT)?;
+        Ok(buf[0])
+    }
+
+    // Config and channel writes move the read pointer, so always point it back at status
+    fn wait_idle(&mut self) -> Result<u8, EspError> {
+        self.i2c.write(
+            self.addr,
+            &[CMD_SET_READ_PTR, REG_STATUS],
+            DS2482_I2C_TIMEOUT,
+        )?;
+        for _ in 0..DS2482_BUSY_POLLS {
+            let status = self.read_byte()?;
+            if status & STATUS_1WB == 0 {
+                return Ok(status);
+            }
+            Ets::delay_us(20);
+        }
+        Err(EspError::from_infallible::<ESP_ERR_TIMEOUT>())
+    }
+
+    fn write_config(&mut self, config: u8) -> Result<(), EspError> {
+        self.wait_idle()?;
+        let config = config & 0x0F;
+        self.i2c.write(
+            self.addr,
+            &[CMD_WRITE_CONFIG, config | (!config << 4)],
+            DS2482_I2C_TIMEOUT,
+        )
+    }
+
+    fn select_channel(&mut self, channel: u8) -> Result<(), EspError> {
+        let (code, readback) = CHANNEL_CODES[channel as usize];
+        self.wait_idle()?;
+        self.i2c
+            .write(self.addr, &[CMD_CHANNEL_SELECT, code], DS2482_I2C_TIMEOUT)?;
+        if self.read_byte()? != readback {
+            return Err(EspError::from_infallible::<ESP_ERR_NOT_FOUND>());
+        }
+        self.channel = channel;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), EspError> {
+        self.wait_idle()?;
+        self.i2c
+            .write(self.addr, &[CMD_1WIRE_RESET], DS2482_I2C_TIMEOUT)?;
+        let status = self.wait_idle()?;
+        if status & STATUS_PPD == 0 {
+            return Err(EspError::from_infallible::<ESP_ERR_NOT_FOUND>());
+        }
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), EspError> {
+        self.wait_idle()?;
+        self.i2c
+            .write(self.addr, &[CMD_1WIRE_WRITE_BYTE, byte], DS2482_I2C_TIMEOUT)
+    }
+
+    fn read_1wire_byte(&mut self) -> Result<u8, EspError> {
+        self.wait_idle()?;
+        self.i2c
+            .write(self.addr, &[CMD_1WIRE_READ_BYTE], DS2482_I2C_TIMEOUT)?;
+        self.wait_idle()?;
+        self.i2c
+            .write(self.addr, &[CMD_SET_READ_PTR, REG_DATA], DS2482_I2C_TIMEOUT)?;
+        self.read_byte()
+    }
+
+    // Returns (id_bit, cmp_id_bit, taken direction)
+    fn triplet(&mut self, direction: bool) -> Result<(bool, bool, bool), EspError> {
+        self.wait_idle()?;
+        self.i2c.write(
+            self.addr,
+            &[CMD_1WIRE_TRIPLET, if direction { 0x80 } else { 0x00 }],
+            DS2482_I2C_TIMEOUT,
+        )?;
+        let status = self.wait_idle()?;
+        Ok((
+            status & STATUS_SBR != 0,
+            status & STATUS_TSB != 0,
+            status & STATUS_DIR != 0,
+        ))
+    }
+}
+
+impl TripletSearch for Ds2482Inner {
+    fn search_reset(&mut self) -> Result<(), EspError> {
+        self.reset()
+    }
+
+    fn search_write_byte(&mut self, byte: u8) -> Result<(), EspError> {
+        self.write_byte(byte)
+    }
+
+    fn search_triplet(&mut self, direction: bool) -> Result<(bool, bool, bool), EspError> {
+        self.triplet(direction)
+    }
+}
+
+#[derive(Clone)]
+pub struct Ds2482Channel {
+    bridge: Arc<Ds2482>,
+    channel: u8,
+}
+
+impl Ds2482Channel {
+    pub fn new(bridge: Arc<Ds2482>, channel: u8) -> Self {
+        Self { bridge, channel }
+    }
+}
+
+impl OneWireBus for Ds2482Channel {
+    fn reset(&self) -> Result<(), EspError> {
+        self.bridge
+            .with_channel(self.channel, |inner| inner.reset())
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<(), EspError> {
+        self.bridge.with_channel(self.channel, |inner| {
+            for b in buf.iter_mut() {
+                *b = inner.read_1wire_byte()?;
+            }
+            Ok(())
+        })
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), EspError> {
+        self.bridge.with_channel(self.channel, |inner| {
+            for (i, b) in data.iter().enumerate() {
+                // SPU only applies to the byte following it, so arm it for the last one
+                if inner.spu_armed && i == data.len() - 1 {
+                    inner.write_config(CONFIG_APU | CONFIG_SPU)?;
+                    inner.spu_armed = false;
+                }
+                inner.write_byte(*b)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn search_devices(&mut self) -> Result<Vec<OWAddress>, EspError> {
+        self.bridge
+            .with_channel(self.channel, |inner| inner.search_rom())
+    }
+
+    fn strong_pullup(&self, enable: bool) -> Result<(), EspError> {
+        self.bridge.with_channel(self.channel, |inner| {
+            inner.spu_armed = enable;
+            if !enable {
+                // writing the config register ends an active strong pull-up
+                inner.write_config(CONFIG_APU)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// EOF
@@ -16,6 +16,12 @@ const API_VERSION_MINOR: u32 = 14;
 const STATE_CLASS_NONE: u32 = 0;
 const STATE_CLASS_MEASUREMENT: u32 = 1;
 
+const THERMO_MODE_OPTIONS: [&str; 3] = [
+    ThermoMode::ALL[0].as_str(),
+    ThermoMode::ALL[1].as_str(),
+    ThermoMode::ALL[2].as_str(),
+];
+
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum ApiMessageType {
@@ -29,14 +35,22 @@ enum ApiMessageType {
     DeviceInfoRequest = 9,
     DeviceInfoResponse = 10,
     ListEntitiesRequest = 11,
+    ListEntitiesBinarySensorResponse = 12,
     ListEntitiesSensorResponse = 16,
     ListEntitiesTextSensorResponse = 18,
     ListEntitiesDoneResponse = 19,
     SubscribeStatesRequest = 20,
+    BinarySensorStateResponse = 21,
     SensorStateResponse = 25,
     TextSensorStateResponse = 27,
     SubscribeHomeassistantServicesRequest = 34,
     SubscribeHomeassistantStatesRequest = 38,
+    ListEntitiesNumberResponse = 49,
+    NumberStateResponse = 50,
+    NumberCommandRequest = 51,
+    ListEntitiesSelectResponse = 52,
+    SelectStateResponse = 53,
+    SelectCommandRequest = 54,
     NoiseEncryptionSetKeyRequest = 124,
     NoiseEncryptionSetKeyResponse = 125,
 }
@@ -62,14 +76,22 @@ impl TryFrom<u32> for ApiMessageType {
             9 => Ok(Self::DeviceInfoRequest),
             10 => Ok(Self::DeviceInfoResponse),
             11 => Ok(Self::ListEntitiesRequest),
+            12 => Ok(Self::ListEntitiesBinarySensorResponse),
             16 => Ok(Self::ListEntitiesSensorResponse),
             18 => Ok(Self::ListEntitiesTextSensorResponse),
             19 => Ok(Self::ListEntitiesDoneResponse),
             20 => Ok(Self::SubscribeStatesRequest),
+            21 => Ok(Self::BinarySensorStateResponse),
             25 => Ok(Self::SensorStateResponse),
             27 => Ok(Self::TextSensorStateResponse),
             34 => Ok(Self::SubscribeHomeassistantServicesRequest),
             38 => Ok(Self::SubscribeHomeassistantStatesRequest),
+            49 => Ok(Self::ListEntitiesNumberResponse),
+            50 => Ok(Self::NumberStateResponse),
+            51 => Ok(Self::NumberCommandRequest),
+            52 => Ok(Self::ListEntitiesSelectResponse),
+            53 => Ok(Self::SelectStateResponse),
+            54 => Ok(Self::SelectCommandRequest),
             124 => Ok(Self::NoiseEncryptionSetKeyRequest),
             125 => Ok(Self::NoiseEncryptionSetKeyResponse),
             _ => Err(()),
@@ -77,10 +99,13 @@ impl TryFrom<u32> for ApiMessageType {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum EntityKind {
     Sensor,
     TextSensor,
+    BinarySensor,
+    Number { min: f32, max: f32, step: f32 },
+    Select { options: &'static [&'static str] },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -88,6 +113,58 @@ enum EntitySource {
     Uptime,
     LastUpdate,
     Temperature { address_hex: String },
+    RateMin { address_hex: String },
+    RateHour { address_hex: String },
+    Trend { address_hex: String },
+    ReadTime { address_hex: String },
+    Retries { address_hex: String },
+    ConversionTime { address_hex: String },
+    DegreeDays(DegreeDayField),
+    ThermostatRelay,
+    ThermostatMode,
+    ThermostatSetpoint,
+    PwmDuty,
+    PwmSetpoint,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DegreeDayField {
+    HeatToday,
+    CoolToday,
+    HeatYesterday,
+    CoolYesterday,
+    HeatMonth,
+    CoolMonth,
+}
+
+impl DegreeDayField {
+    const ALL: [(DegreeDayField, &'static str, &'static str); 6] = [
+        (Self::HeatToday, "hdd_today", "Heating Degree Days Today"),
+        (Self::CoolToday, "cdd_today", "Cooling Degree Days Today"),
+        (
+            Self::HeatYesterday,
+            "hdd_yesterday",
+            "Heating Degree Days Yesterday",
+        ),
+        (
+            Self::CoolYesterday,
+            "cdd_yesterday",
+            "Cooling Degree Days Yesterday",
+        ),
+        (Self::HeatMonth, "hdd_month", "Heating Degree Days Month"),
+        (Self::CoolMonth, "cdd_month", "Cooling Degree Days Month"),
+    ];
+
+    fn value(self, dd: &DegreeDayTotals) -> f32 {
+        match self {
+            Self::HeatToday => dd.heat_today,
+            Self::CoolToday => dd.cool_today,
+            Self::HeatYesterday => dd.heat_yesterday,
+            Self::CoolYesterday => dd.cool_yesterday,
+            Self::HeatMonth => dd.heat_month,
+            Self::CoolMonth => dd.cool_month,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -108,6 +185,13 @@ enum EntityStateValue {
     Missing,
     Number(f32),
     Text(String),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum CommandValue {
+    Number(f32),
+    Text(String),
 }
 
 pub async fn run_esphome_api(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
@@ -196,6 +280,15 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
                     ))
                     .await?;
                 }
+                Ok(ApiMessageType::NumberCommandRequest)
+                | Ok(ApiMessageType::SelectCommandRequest) => {
+                    match parse_command_request(&payload) {
+                        Some((key, value)) => {
+                            Box::pin(handle_command(&state, &entities, key, value)).await;
+                        }
+                        None => warn!("ESPHome API: malformed command request"),
+                    }
+                }
                 Ok(ApiMessageType::SubscribeHomeassistantServicesRequest)
                 | Ok(ApiMessageType::SubscribeHomeassistantStatesRequest) => continue,
                 Ok(ApiMessageType::NoiseEncryptionSetKeyRequest) => {
@@ -241,6 +334,53 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
     }
 }
 
+async fn handle_command(
+    state: &Arc<Pin<Box<MyState>>>,
+    entities: &[EntityDef],
+    key: u32,
+    value: CommandValue,
+) {
+    let Some(entity) = entities.iter().find(|e| e.key == key) else {
+        warn!("ESPHome API: command for unknown entity key {key}");
+        return;
+    };
+
+    let result = match (&entity.source, value) {
+        (EntitySource::ThermostatMode, CommandValue::Text(mode)) => {
+            let Some(mode) = ThermoMode::from_name(&mode) else {
+                warn!("ESPHome API: unknown thermostat mode {mode:?}");
+                return;
+            };
+            let cmd = ThermostatCommand {
+                mode: Some(mode),
+                setpoint: None,
+            };
+            set_thermostat(state, cmd).await.map(drop)
+        }
+        (EntitySource::ThermostatSetpoint, CommandValue::Number(setpoint)) => {
+            let cmd = ThermostatCommand {
+                mode: None,
+                setpoint: Some(setpoint),
+            };
+            set_thermostat(state, cmd).await.map(drop)
+        }
+        (EntitySource::PwmSetpoint, CommandValue::Number(setpoint)) => {
+            let cmd = PwmCommand {
+                setpoint: Some(setpoint),
+            };
+            set_pwm_control(state, cmd).await.map(drop)
+        }
+        (source, value) => {
+            warn!("ESPHome API: unsupported command {value:?} for {source:?}");
+            return;
+        }
+    };
+
+    if let Err(e) = result {
+        error!("ESPHome API: {} command failed: {e:#}", entity.object_id);
+    }
+}
+
 async fn send_hello_response(
     state: &Arc<Pin<Box<MyState>>>,
     stream: &mut TcpStream,
@@ -311,6 +451,47 @@ async fn send_list_entities_response(
                 )
                 .await?;
             }
+            EntityKind::BinarySensor => {
+                let mut payload = Vec::new();
+                pb_put_string(1, &entity.object_id, &mut payload);
+                pb_put_fixed32(2, entity.key, &mut payload);
+                pb_put_string(3, &entity.name, &mut payload);
+                if let Some(device_class) = &entity.device_class {
+                    pb_put_string(5, device_class, &mut payload);
+                }
+                send_frame(
+                    stream,
+                    ApiMessageType::ListEntitiesBinarySensorResponse,
+                    &payload,
+                )
+                .await?;
+            }
+            EntityKind::Number { min, max, step } => {
+                let mut payload = Vec::new();
+                pb_put_string(1, &entity.object_id, &mut payload);
+                pb_put_fixed32(2, entity.key, &mut payload);
+                pb_put_string(3, &entity.name, &mut payload);
+                pb_put_float(6, min, &mut payload);
+                pb_put_float(7, max, &mut payload);
+                pb_put_float(8, step, &mut payload);
+                if let Some(unit) = &entity.unit {
+                    pb_put_string(11, unit, &mut payload);
+                }
+                if let Some(device_class) = &entity.device_class {
+                    pb_put_string(13, device_class, &mut payload);
+                }
+                send_frame(stream, ApiMessageType::ListEntitiesNumberResponse, &payload).await?;
+            }
+            EntityKind::Select { options } => {
+                let mut payload = Vec::new();
+                pb_put_string(1, &entity.object_id, &mut payload);
+                pb_put_fixed32(2, entity.key, &mut payload);
+                pb_put_string(3, &entity.name, &mut payload);
+                for option in options {
+                    pb_put_string(6, option, &mut payload);
+                }
+                send_frame(stream, ApiMessageType::ListEntitiesSelectResponse, &payload).await?;
+            }
         }
     }
 
@@ -346,7 +527,8 @@ async fn send_state_updates(
                 send_frame(stream, ApiMessageType::SensorStateResponse, &payload).await?;
             }
             (EntityKind::Sensor, EntityStateValue::Missing)
-            | (EntityKind::Sensor, EntityStateValue::Text(_)) => {
+            | (EntityKind::Sensor, EntityStateValue::Text(_))
+            | (EntityKind::Sensor, EntityStateValue::Bool(_)) => {
                 let mut payload = Vec::new();
                 pb_put_fixed32(1, entity.key, &mut payload);
                 pb_put_bool(3, true, &mut payload);
@@ -364,12 +546,54 @@ async fn send_state_updates(
                 pb_put_string(2, &v.to_string(), &mut payload);
                 send_frame(stream, ApiMessageType::TextSensorStateResponse, &payload).await?;
             }
+            (EntityKind::TextSensor, EntityStateValue::Bool(v)) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_string(2, if *v { "on" } else { "off" }, &mut payload);
+                send_frame(stream, ApiMessageType::TextSensorStateResponse, &payload).await?;
+            }
             (EntityKind::TextSensor, EntityStateValue::Missing) => {
                 let mut payload = Vec::new();
                 pb_put_fixed32(1, entity.key, &mut payload);
                 pb_put_bool(3, true, &mut payload);
                 send_frame(stream, ApiMessageType::TextSensorStateResponse, &payload).await?;
             }
+            (EntityKind::BinarySensor, EntityStateValue::Bool(v)) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_bool(2, *v, &mut payload);
+                send_frame(stream, ApiMessageType::BinarySensorStateResponse, &payload).await?;
+            }
+            (EntityKind::BinarySensor, _) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_bool(3, true, &mut payload);
+                send_frame(stream, ApiMessageType::BinarySensorStateResponse, &payload).await?;
+            }
+            (EntityKind::Number { .. }, EntityStateValue::Number(v)) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_float(2, *v, &mut payload);
+                send_frame(stream, ApiMessageType::NumberStateResponse, &payload).await?;
+            }
+            (EntityKind::Number { .. }, _) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_bool(3, true, &mut payload);
+                send_frame(stream, ApiMessageType::NumberStateResponse, &payload).await?;
+            }
+            (EntityKind::Select { .. }, EntityStateValue::Text(v)) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_string(2, v, &mut payload);
+                send_frame(stream, ApiMessageType::SelectStateResponse, &payload).await?;
+            }
+            (EntityKind::Select { .. }, _) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_bool(3, true, &mut payload);
+                send_frame(stream, ApiMessageType::SelectStateResponse, &payload).await?;
+            }
         }
 
         last_sent.insert(entity.key, value);
@@ -380,6 +604,7 @@ async fn send_state_updates(
 
 async fn build_entity_defs(state: &Arc<Pin<Box<MyState>>>) -> Vec<EntityDef> {
     let sensors = state.sensors.read().await;
+    let unit = state.config.unit.symbol();
     let mut entities = Vec::new();
 
     entities.push(EntityDef {
@@ -420,11 +645,186 @@ async fn build_entity_defs(state: &Arc<Pin<Box<MyState>>>) -> Vec<EntityDef> {
                 object_id,
                 name: format!("Temperature {} {}", onewire.name, address_hex),
                 kind: EntityKind::Sensor,
-                unit: Some("\u{00B0}C".into()),
+                unit: Some(unit.into()),
                 accuracy: 2,
                 device_class: Some("temperature".into()),
                 state_class: STATE_CLASS_MEASUREMENT,
             });
+
+            let addr = address_hex.to_ascii_lowercase();
+            let object_id = format!("rate_min_{addr}");
+            entities.push(EntityDef {
+                source: EntitySource::RateMin {
+                    address_hex: address_hex.clone(),
+                },
+                key: stable_key(&object_id),
+                object_id,
+                name: format!("Rate {} {}", onewire.name, address_hex),
+                kind: EntityKind::Sensor,
+                unit: Some(format!("{unit}/min")),
+                accuracy: 3,
+                device_class: None,
+                state_class: STATE_CLASS_MEASUREMENT,
+            });
+            let object_id = format!("rate_h_{addr}");
+            entities.push(EntityDef {
+                source: EntitySource::RateHour {
+                    address_hex: address_hex.clone(),
+                },
+                key: stable_key(&object_id),
+                object_id,
+                name: format!("Hourly Rate {} {}", onewire.name, address_hex),
+                kind: EntityKind::Sensor,
+                unit: Some(format!("{unit}/h")),
+                accuracy: 2,
+                device_class: None,
+                state_class: STATE_CLASS_MEASUREMENT,
+            });
+            let object_id = format!("trend_{addr}");
+            entities.push(EntityDef {
+                source: EntitySource::Trend {
+                    address_hex: address_hex.clone(),
+                },
+                key: stable_key(&object_id),
+                object_id,
+                name: format!("Trend {} {}", onewire.name, address_hex),
+                kind: EntityKind::TextSensor,
+                unit: None,
+                accuracy: 0,
+                device_class: None,
+                state_class: STATE_CLASS_NONE,
+            });
+            let object_id = format!("read_time_{addr}");
+            entities.push(EntityDef {
+                source: EntitySource::ReadTime {
+                    address_hex: address_hex.clone(),
+                },
+                key: stable_key(&object_id),
+                object_id,
+                name: format!("Read Time {} {}", onewire.name, address_hex),
+                kind: EntityKind::TextSensor,
+                unit: None,
+                accuracy: 0,
+                device_class: Some("timestamp".into()),
+                state_class: STATE_CLASS_NONE,
+            });
+            let object_id = format!("retries_{addr}");
+            entities.push(EntityDef {
+                source: EntitySource::Retries {
+                    address_hex: address_hex.clone(),
+                },
+                key: stable_key(&object_id),
+                object_id,
+                name: format!("Retries {} {}", onewire.name, address_hex),
+                kind: EntityKind::Sensor,
+                unit: None,
+                accuracy: 0,
+                device_class: None,
+                state_class: STATE_CLASS_MEASUREMENT,
+            });
+            let object_id = format!("conversion_{addr}");
+            entities.push(EntityDef {
+                source: EntitySource::ConversionTime {
+                    address_hex: address_hex.clone(),
+                },
+                key: stable_key(&object_id),
+                object_id,
+                name: format!("Conversion Time {} {}", onewire.name, address_hex),
+                kind: EntityKind::Sensor,
+                unit: Some("ms".into()),
+                accuracy: 0,
+                device_class: Some("duration".into()),
+                state_class: STATE_CLASS_MEASUREMENT,
+            });
+        }
+    }
+
+    if state.config.thermo_enable {
+        entities.push(EntityDef {
+            source: EntitySource::ThermostatRelay,
+            key: stable_key("thermostat_relay"),
+            object_id: "thermostat_relay".into(),
+            name: "Thermostat Relay".into(),
+            kind: EntityKind::BinarySensor,
+            unit: None,
+            accuracy: 0,
+            device_class: Some("power".into()),
+            state_class: STATE_CLASS_NONE,
+        });
+        entities.push(EntityDef {
+            source: EntitySource::ThermostatMode,
+            key: stable_key("thermostat_mode"),
+            object_id: "thermostat_mode".into(),
+            name: "Thermostat Mode".into(),
+            kind: EntityKind::Select {
+                options: &THERMO_MODE_OPTIONS,
+            },
+            unit: None,
+            accuracy: 0,
+            device_class: None,
+            state_class: STATE_CLASS_NONE,
+        });
+        let u = state.config.unit;
+        entities.push(EntityDef {
+            source: EntitySource::ThermostatSetpoint,
+            key: stable_key("thermostat_setpoint"),
+            object_id: "thermostat_setpoint".into(),
+            name: "Thermostat Setpoint".into(),
+            kind: EntityKind::Number {
+                min: u.from_celsius(THERMO_SETPOINT_MIN),
+                max: u.from_celsius(THERMO_SETPOINT_MAX),
+                step: 0.5,
+            },
+            unit: Some(unit.into()),
+            accuracy: 1,
+            device_class: Some("temperature".into()),
+            state_class: STATE_CLASS_NONE,
+        });
+    }
+
+    if state.config.pwm_enable {
+        entities.push(EntityDef {
+            source: EntitySource::PwmDuty,
+            key: stable_key("pwm_duty"),
+            object_id: "pwm_duty".into(),
+            name: "PWM Duty".into(),
+            kind: EntityKind::Sensor,
+            unit: Some("%".into()),
+            accuracy: 1,
+            device_class: None,
+            state_class: STATE_CLASS_MEASUREMENT,
+        });
+        let u = state.config.unit;
+        entities.push(EntityDef {
+            source: EntitySource::PwmSetpoint,
+            key: stable_key("pwm_setpoint"),
+            object_id: "pwm_setpoint".into(),
+            name: "PWM Setpoint".into(),
+            kind: EntityKind::Number {
+                min: u.from_celsius(THERMO_SETPOINT_MIN),
+                max: u.from_celsius(THERMO_SETPOINT_MAX),
+                step: 0.5,
+            },
+            unit: Some(unit.into()),
+            accuracy: 1,
+            device_class: Some("temperature".into()),
+            state_class: STATE_CLASS_NONE,
+        });
+    }
+
+    if !state.config.dd_sensor.is_empty() {
+        for (field, object_id, name) in DegreeDayField::ALL {
+            entities.push(EntityDef {
+                source: EntitySource::DegreeDays(field),
+                key: stable_key(object_id),
+                object_id: object_id.into(),
+                name: name.into(),
+                kind: EntityKind::Sensor,
+                unit: Some(format!("{unit}\u{00B7}d")),
+                accuracy: 2,
+                device_class: None,
+                state_class: STATE_CLASS_MEASUREMENT,
+            });
         }
     }
 
@@ -436,10 +836,14 @@ async fn build_entity_states(
     entities: &[EntityDef],
 ) -> BTreeMap<u32, EntityStateValue> {
     let data = state.data.read().await.clone();
+    let unit = state.config.unit;
+    let degree_days = state.degree_days.read().await.in_unit(unit);
+    let thermostat = state.thermostat.read().await.status(&state.config);
+    let pwm = state.pwm.read().await.status(&state.config);
     let mut temp_map = BTreeMap::new();
     for temp in data.temperatures.iter() {
         if temp.value > NO_TEMP {
-            temp_map.insert(temp.sensor.clone(), temp.value);
+            temp_map.insert(temp.sensor.clone(), temp.in_unit(unit));
         }
     }
 
@@ -455,9 +859,48 @@ async fn build_entity_states(
                 }
             }
             EntitySource::Temperature { address_hex } => match temp_map.get(address_hex) {
-                Some(value) => EntityStateValue::Number(*value),
+                Some(temp) => EntityStateValue::Number(temp.value),
+                None => EntityStateValue::Missing,
+            },
+            EntitySource::RateMin { address_hex } => {
+                match temp_map.get(address_hex).and_then(|t| t.rate_min) {
+                    Some(rate) => EntityStateValue::Number(rate),
+                    None => EntityStateValue::Missing,
+                }
+            }
+            EntitySource::RateHour { address_hex } => {
+                match temp_map.get(address_hex).and_then(|t| t.rate_h) {
+                    Some(rate) => EntityStateValue::Number(rate),
+                    None => EntityStateValue::Missing,
+                }
+            }
+            EntitySource::Trend { address_hex } => match temp_map.get(address_hex) {
+                Some(temp) if temp.trend != Trend::Unknown => {
+                    EntityStateValue::Text(temp.trend.as_str().into())
+                }
+                _ => EntityStateValue::Missing,
+            },
+            EntitySource::ReadTime { address_hex } => match temp_map
+                .get(address_hex)
+                .and_then(|t| DateTime::from_timestamp(t.timestamp, 0))
+            {
+                Some(ts) => EntityStateValue::Text(ts.to_rfc3339()),
+                None => EntityStateValue::Missing,
+            },
+            EntitySource::Retries { address_hex } => match temp_map.get(address_hex) {
+                Some(temp) => EntityStateValue::Number(temp.retries as f32),
+                None => EntityStateValue::Missing,
+            },
+            EntitySource::ConversionTime { address_hex } => match temp_map.get(address_hex) {
+                Some(temp) => EntityStateValue::Number(temp.conversion_ms as f32),
                 None => EntityStateValue::Missing,
             },
+            EntitySource::DegreeDays(field) => EntityStateValue::Number(field.value(&degree_days)),
+            EntitySource::ThermostatRelay => EntityStateValue::Bool(thermostat.relay_on),
+            EntitySource::ThermostatMode => EntityStateValue::Text(thermostat.mode.to_string()),
+            EntitySource::ThermostatSetpoint => EntityStateValue::Number(thermostat.setpoint),
+            EntitySource::PwmDuty => EntityStateValue::Number(pwm.duty),
+            EntitySource::PwmSetpoint => EntityStateValue::Number(pwm.setpoint),
         };
         out.insert(entity.key, value);
     }
@@ -563,6 +1006,51 @@ fn parse_hello_request(payload: &[u8]) -> Option<(String, u32, u32)> {
     Some((client_info, major, minor))
 }
 
+// NumberCommandRequest and SelectCommandRequest: fixed32 key in field 1, state in field 2
+fn parse_command_request(payload: &[u8]) -> Option<(u32, CommandValue)> {
+    let mut idx = 0_usize;
+    let mut key = None;
+    let mut value = None;
+
+    while idx < payload.len() {
+        let tag = read_varuint_from_slice(payload, &mut idx)?;
+        let field_number = (tag >> 3) as u32;
+        let wire_type = (tag & 0x07) as u8;
+        match wire_type {
+            0 => {
+                read_varuint_from_slice(payload, &mut idx)?;
+            }
+            2 => {
+                let len = read_varuint_from_slice(payload, &mut idx)? as usize;
+                if idx + len > payload.len() {
+                    return None;
+                }
+                if field_number == 2 {
+                    let s = std::str::from_utf8(&payload[idx..idx + len]).ok()?;
+                    value = Some(CommandValue::Text(s.to_string()));
+                }
+                idx += len;
+            }
+            5 => {
+                let bytes: [u8; 4] = payload.get(idx..idx + 4)?.try_into().ok()?;
+                match field_number {
+                    1 => key = Some(u32::from_le_bytes(bytes)),
+                    2 => value = Some(CommandValue::Number(f32::from_le_bytes(bytes))),
+                    _ => {}
+                }
+                idx += 4;
+            }
+            1 => idx += 8,
+            _ => return None,
+        }
+        if idx > payload.len() {
+            return None;
+        }
+    }
+
+    Some((key?, value?))
+}
+
 fn read_varuint_from_slice(data: &[u8], idx: &mut usize) -> Option<u64> {
     let mut out = 0_u64;
     let mut shift = 0_u32;
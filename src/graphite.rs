@@ -0,0 +1,165 @@
+// graphite.rs
+//
+// Optional Graphite outputs: Carbon plaintext over TCP (`path value timestamp`) and
+// StatsD gauges over UDP (`path:value|g`). Both send the readings taken since the
+// previous poll cycle, under a metric prefix from `graphite_prefix`.
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+};
+
+use crate::*;
+
+const GRAPHITE_TICK_S: u64 = 5;
+const CARBON_TIMEOUT_S: u64 = 10;
+// keep StatsD datagrams within a typical MTU
+const STATSD_MAX_DATAGRAM: usize = 1400;
+
+// Characters that would split or break a metric path become '_'
+fn path_component(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Metric prefix from `graphite_prefix`, `{myid}` is replaced with the device id
+fn metric_prefix(config: &MyConfig, myid: &str) -> String {
+    config
+        .graphite_prefix
+        .replace("{myid}", &path_component(myid))
+        .trim_matches('.')
+        .to_string()
+}
+
+async fn send_carbon(host: &str, port: u16, lines: &str) -> anyhow::Result<()> {
+    let mut stream = match timeout(
+        Duration::from_secs(CARBON_TIMEOUT_S),
+        TcpStream::connect((host, port)),
+    )
+    .await
+    {
+        Ok(stream) => stream?,
+        Err(_) => bail!("connect timed out"),
+    };
+    stream.write_all(lines.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn send_statsd(host: &str, port: u16, metrics: &[String]) -> anyhow::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect((host, port)).await?;
+    // several metrics per datagram, one per line
+    let mut datagram = String::new();
+    for m in metrics.iter() {
+        if !datagram.is_empty() && datagram.len() + m.len() + 1 > STATSD_MAX_DATAGRAM {
+            socket.send(datagram.as_bytes()).await?;
+            datagram.clear();
+        }
+        if !datagram.is_empty() {
+            datagram.push('\n');
+        }
+        datagram += m;
+    }
+    if !datagram.is_empty() {
+        socket.send(datagram.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+pub async fn run_graphite(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    let config = &state.config;
+    let carbon = config.graphite_enable && !config.graphite_host.trim().is_empty();
+    let statsd = config.statsd_enable && !config.statsd_host.trim().is_empty();
+    if state.ap_mode || !(carbon || statsd) {
+        info!("Graphite/StatsD output is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let unit = config.unit;
+    if carbon {
+        info!(
+            "Carbon output to {}:{}",
+            config.graphite_host, config.graphite_port
+        );
+    }
+    if statsd {
+        info!(
+            "StatsD output to {}:{}",
+            config.statsd_host, config.statsd_port
+        );
+    }
+
+    let mut readings = NewReadings::default();
+    loop {
+        sleep(Duration::from_secs(GRAPHITE_TICK_S)).await;
+
+        let Some((_, fresh)) = readings.take(&state).await else {
+            continue;
+        };
+        if fresh.is_empty() {
+            continue;
+        }
+
+        // myid is only final once WiFi is up
+        let prefix = metric_prefix(config, &state.myid.read().await);
+        let metrics = fresh
+            .iter()
+            .map(|t| {
+                let name = config.sensor_alias(&t.sensor).unwrap_or(&t.sensor);
+                let path = if prefix.is_empty() {
+                    path_component(name)
+                } else {
+                    format!("{prefix}.{}", path_component(name))
+                };
+                (path, unit.from_celsius(t.value), t.timestamp)
+            })
+            .collect::<Vec<_>>();
+
+        if carbon {
+            let lines = metrics
+                .iter()
+                .map(|(path, value, ts)| format!("{path} {value} {ts}\n"))
+                .collect::<String>();
+            match Box::pin(send_carbon(
+                config.graphite_host.trim(),
+                config.graphite_port,
+                &lines,
+            ))
+            .await
+            {
+                Ok(()) => info!("Carbon: {} metric(s) sent", metrics.len()),
+                Err(e) => error!("Carbon error: {e:#}"),
+            }
+        }
+        if statsd {
+            // a signed gauge value is a delta in StatsD, so negative values are set
+            // by zeroing the gauge first
+            let gauges = metrics
+                .iter()
+                .flat_map(|(path, value, _)| {
+                    let zero = (*value < 0.0).then(|| format!("{path}:0|g"));
+                    zero.into_iter().chain([format!("{path}:{value}|g")])
+                })
+                .collect::<Vec<_>>();
+            if let Err(e) = Box::pin(send_statsd(
+                config.statsd_host.trim(),
+                config.statsd_port,
+                &gauges,
+            ))
+            .await
+            {
+                error!("StatsD error: {e:#}");
+            }
+        }
+    }
+}
+
+// EOF
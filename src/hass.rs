@@ -0,0 +1,121 @@
+// hass.rs
+//
+// Home Assistant MQTT discovery: retained config messages under
+// `{mqtt_discovery_prefix}/sensor/{myid}/{object}/config` describe the temperature
+// sensors and uptime, so HA creates the entities by itself. Keys use HA's documented
+// abbreviations to keep the messages well within the MQTT client buffer.
+
+use crate::*;
+
+#[derive(Clone, Debug, Serialize)]
+struct HassDevice {
+    #[serde(rename = "ids")]
+    identifiers: Vec<String>,
+    #[serde(rename = "cns")]
+    connections: Vec<(&'static str, String)>,
+    name: String,
+    #[serde(rename = "mf")]
+    manufacturer: &'static str,
+    #[serde(rename = "mdl")]
+    model: &'static str,
+    #[serde(rename = "sw")]
+    sw_version: &'static str,
+    #[serde(rename = "cu")]
+    configuration_url: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct HassSensor<'a> {
+    name: String,
+    #[serde(rename = "uniq_id")]
+    unique_id: String,
+    #[serde(rename = "stat_t")]
+    state_topic: String,
+    #[serde(rename = "json_attr_t", skip_serializing_if = "Option::is_none")]
+    json_attributes_topic: Option<String>,
+    #[serde(rename = "val_tpl")]
+    value_template: &'static str,
+    #[serde(rename = "dev_cla")]
+    device_class: &'static str,
+    #[serde(rename = "stat_cla")]
+    state_class: &'static str,
+    #[serde(rename = "unit_of_meas")]
+    unit_of_measurement: &'static str,
+    #[serde(rename = "ent_cat", skip_serializing_if = "Option::is_none")]
+    entity_category: Option<&'static str>,
+    #[serde(rename = "avty_t")]
+    availability_topic: String,
+    #[serde(rename = "dev")]
+    device: &'a HassDevice,
+}
+
+/// Discovery config topic of one entity
+pub fn hass_config_topic(config: &MyConfig, myid: &str, object: &str) -> String {
+    format!(
+        "{}/sensor/{myid}/{object}/config",
+        config.mqtt_discovery_prefix.trim().trim_end_matches('/')
+    )
+}
+
+/// Topics and retained payloads describing the uptime and every sensor in `sensors`
+pub async fn hass_discovery(
+    state: &MyState,
+    sensors: &[String],
+) -> anyhow::Result<Vec<(String, String)>> {
+    let config = &state.config;
+    let topic = &config.mqtt_topic;
+    let myid = state.myid.read().await.clone();
+    let device = HassDevice {
+        identifiers: vec![myid.clone()],
+        connections: vec![("mac", state.my_mac_s.read().await.clone())],
+        name: myid.clone(),
+        manufacturer: "esp32temp",
+        model: "ESP32",
+        sw_version: FW_VERSION,
+        configuration_url: format!("http://{}/", state.ip_addr.read().await),
+    };
+
+    let mut messages = Vec::with_capacity(sensors.len() + 1);
+    let uptime = HassSensor {
+        name: "Uptime".into(),
+        unique_id: format!("{myid}_uptime"),
+        state_topic: format!("{topic}/uptime"),
+        json_attributes_topic: None,
+        value_template: "{{ value_json.uptime }}",
+        device_class: "duration",
+        state_class: "total_increasing",
+        unit_of_measurement: "s",
+        entity_category: Some("diagnostic"),
+        availability_topic: mqtt_status_topic(config),
+        device: &device,
+    };
+    messages.push((
+        hass_config_topic(config, &myid, "uptime"),
+        serde_json::to_string(&uptime)?,
+    ));
+
+    for sensor in sensors.iter() {
+        let state_topic = format!("{topic}/{sensor}");
+        let entity = HassSensor {
+            name: config.sensor_alias(sensor).unwrap_or(sensor).to_string(),
+            unique_id: format!("{myid}_{sensor}"),
+            // rate and trend become attributes
+            json_attributes_topic: Some(state_topic.clone()),
+            state_topic,
+            value_template: "{{ value_json.temperature }}",
+            device_class: "temperature",
+            state_class: "measurement",
+            unit_of_measurement: config.unit.symbol(),
+            entity_category: None,
+            availability_topic: mqtt_status_topic(config),
+            device: &device,
+        };
+        messages.push((
+            hass_config_topic(config, &myid, sensor),
+            serde_json::to_string(&entity)?,
+        ));
+    }
+    Ok(messages)
+}
+
+// EOF
@@ -0,0 +1,130 @@
+// http_worker.rs
+//
+// The ESP-IDF HTTP client is blocking (DNS lookup, TCP/TLS connect, waiting for the
+// response), so outgoing requests are made on a dedicated thread, the same way the
+// 1-Wire buses are driven from `ow_worker.rs`.
+
+use embedded_svc::{
+    http::{Method, client::Client as HttpClient},
+    io::Write,
+};
+use esp_idf_svc::http::client::{Configuration as HttpConfiguration, EspHttpConnection};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::*;
+
+const HTTP_WORKER_STACK_SIZE: usize = 10240;
+const HTTP_TIMEOUT_S: u64 = 10;
+
+struct HttpRequest {
+    method: Method,
+    url: String,
+    content_type: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    resp: oneshot::Sender<anyhow::Result<u16>>,
+}
+
+/// Async handle for the HTTP worker thread, cheap to clone.
+#[derive(Clone)]
+pub struct HttpWorkerClient {
+    tx: mpsc::UnboundedSender<HttpRequest>,
+}
+
+impl HttpWorkerClient {
+    /// POST `body` to `url` with optional extra headers, returns the HTTP status code.
+    pub async fn post(
+        &self,
+        url: &str,
+        content_type: &str,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> anyhow::Result<u16> {
+        self.request(Method::Post, url, content_type, headers, body)
+            .await
+    }
+
+    /// GET `url` with optional extra headers, returns the HTTP status code.
+    pub async fn get(&self, url: &str, headers: Vec<(String, String)>) -> anyhow::Result<u16> {
+        self.request(Method::Get, url, "", headers, Vec::new())
+            .await
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        content_type: &str,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> anyhow::Result<u16> {
+        let (resp, rx) = oneshot::channel();
+        if self
+            .tx
+            .send(HttpRequest {
+                method,
+                url: url.to_string(),
+                content_type: content_type.to_string(),
+                headers,
+                body,
+                resp,
+            })
+            .is_err()
+        {
+            bail!("HTTP worker is not running");
+        }
+        match rx.await {
+            Ok(result) => result,
+            Err(_) => bail!("HTTP worker dropped the request"),
+        }
+    }
+}
+
+pub fn start_http_worker() -> anyhow::Result<HttpWorkerClient> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::Builder::new()
+        .name("http".into())
+        .stack_size(HTTP_WORKER_STACK_SIZE)
+        .spawn(move || http_worker(rx))?;
+    Ok(HttpWorkerClient { tx })
+}
+
+fn http_worker(mut rx: mpsc::UnboundedReceiver<HttpRequest>) {
+    info!("HTTP worker started");
+    while let Some(req) = rx.blocking_recv() {
+        let result = http_request(&req);
+        // the requester may have given up waiting
+        let _ = req.resp.send(result);
+    }
+    error!("HTTP worker channel closed.");
+}
+
+fn http_request(req: &HttpRequest) -> anyhow::Result<u16> {
+    if !req.url.starts_with("http://") && !req.url.starts_with("https://") {
+        bail!("URL must start with http:// or https://");
+    }
+
+    let client_config = HttpConfiguration {
+        crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+        timeout: Some(Duration::from_secs(HTTP_TIMEOUT_S)),
+        ..Default::default()
+    };
+    let mut client = HttpClient::wrap(EspHttpConnection::new(&client_config)?);
+
+    let len = req.body.len().to_string();
+    let mut headers = Vec::new();
+    if req.method != Method::Get {
+        headers.push(("content-type", req.content_type.as_str()));
+        headers.push(("content-length", len.as_str()));
+    }
+    headers.extend(req.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    let mut request = client.request(req.method, &req.url, &headers)?;
+    if !req.body.is_empty() {
+        request.write_all(&req.body)?;
+    }
+    request.flush()?;
+    let resp = request.submit()?;
+    Ok(resp.status())
+}
+
+// EOF
@@ -0,0 +1,164 @@
+// influx.rs
+//
+// Optional push of readings to InfluxDB in line protocol, either v1 `/write` or
+// v2 `/api/v2/write` with token auth. Readings taken since the last push are
+// collected after every poll cycle and written in batches of `influx_batch` cycles.
+// Failed writes are kept and retried with exponential backoff. The line format is
+// in `influx_line.rs`.
+
+use std::{collections::VecDeque, time::Instant};
+
+use crate::*;
+
+const INFLUX_TICK_S: u64 = 5;
+// oldest lines are dropped beyond this while the server is unreachable
+const INFLUX_MAX_LINES: usize = 500;
+const INFLUX_BACKOFF_MIN_S: u64 = 5;
+const INFLUX_BACKOFF_MAX_S: u64 = 300;
+
+// Static tags from `influx_tags`, e.g. "site=home,floor=1"
+fn static_tags(config: &MyConfig) -> Vec<(String, String)> {
+    config
+        .influx_tags
+        .split(',')
+        .filter_map(|entry| {
+            let (k, v) = entry.split_once('=')?;
+            let (k, v) = (k.trim(), v.trim());
+            (!k.is_empty() && !v.is_empty()).then(|| (k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+fn write_url(config: &MyConfig) -> String {
+    let base = config.influx_url.trim().trim_end_matches('/');
+    if config.influx_v2 {
+        format!(
+            "{base}/api/v2/write?org={}&bucket={}&precision=s",
+            url_encode(&config.influx_org),
+            url_encode(&config.influx_db)
+        )
+    } else {
+        let mut url = format!(
+            "{base}/write?db={}&precision=s",
+            url_encode(&config.influx_db)
+        );
+        if !config.influx_user.is_empty() {
+            url += &format!(
+                "&u={}&p={}",
+                url_encode(&config.influx_user),
+                url_encode(&config.influx_pass)
+            );
+        }
+        url
+    }
+}
+
+pub async fn run_influx(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if state.ap_mode || !state.config.influx_enable {
+        info!("InfluxDB push is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let config = &state.config;
+    let url = write_url(config);
+    let mut headers = Vec::new();
+    if config.influx_v2 && !config.influx_token.is_empty() {
+        headers.push((
+            "authorization".to_string(),
+            format!("Token {}", config.influx_token),
+        ));
+    }
+    let tags = static_tags(config);
+    let batch = config.influx_batch.max(1);
+    let unit = config.unit;
+    let unit_s = unit.to_string();
+    info!(
+        "InfluxDB push to {} every {batch} poll cycle(s)",
+        config.influx_url
+    );
+
+    let mut readings = NewReadings::default();
+    let mut lines = VecDeque::<String>::new();
+    let mut cycles = 0;
+    let mut backoff = INFLUX_BACKOFF_MIN_S;
+    let mut retry_at: Option<Instant> = None;
+    loop {
+        sleep(Duration::from_secs(INFLUX_TICK_S)).await;
+
+        if let Some((_, fresh)) = readings.take(&state).await {
+            cycles += 1;
+            for t in fresh.iter() {
+                let alias = config.sensor_alias(&t.sensor).unwrap_or_default();
+                let mut line_tags = vec![
+                    ("sensor", t.sensor.as_str()),
+                    ("pin", t.iopin.as_str()),
+                    ("alias", alias),
+                    ("unit", unit_s.as_str()),
+                ];
+                line_tags.extend(tags.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+                lines.push_back(influx_line(
+                    &config.influx_measurement,
+                    &line_tags,
+                    unit.from_celsius(t.value),
+                    t.timestamp,
+                ));
+            }
+        }
+
+        if lines.len() > INFLUX_MAX_LINES {
+            let dropped = lines.len() - INFLUX_MAX_LINES;
+            lines.drain(..dropped);
+            error!("InfluxDB buffer full, dropped {dropped} oldest line(s)");
+        }
+        if lines.is_empty() || cycles < batch || retry_at.is_some_and(|at| Instant::now() < at) {
+            continue;
+        }
+
+        let body = lines.make_contiguous().join("\n");
+        let result = state
+            .http
+            .post(
+                &url,
+                "text/plain; charset=utf-8",
+                headers.clone(),
+                body.into_bytes(),
+            )
+            .await;
+        let retry = match result {
+            Ok(status) if (200..300).contains(&status) => {
+                info!("InfluxDB push: {} line(s) written", lines.len());
+                false
+            }
+            // the data itself was refused, retrying would not help
+            Ok(status) if (400..500).contains(&status) && status != 429 => {
+                error!(
+                    "InfluxDB push rejected with HTTP {status}, dropping {} line(s)",
+                    lines.len()
+                );
+                false
+            }
+            Ok(status) => {
+                error!("InfluxDB push failed with HTTP {status}, retry in {backoff} s");
+                true
+            }
+            Err(e) => {
+                error!("InfluxDB push error: {e:#}, retry in {backoff} s");
+                true
+            }
+        };
+
+        if retry {
+            retry_at = Some(Instant::now() + Duration::from_secs(backoff));
+            backoff = (backoff * 2).min(INFLUX_BACKOFF_MAX_S);
+        } else {
+            lines.clear();
+            cycles = 0;
+            backoff = INFLUX_BACKOFF_MIN_S;
+            retry_at = None;
+        }
+    }
+}
+
+// EOF
@@ -0,0 +1,75 @@
+// influx_line.rs
+//
+// InfluxDB line protocol formatting, kept free of ESP-IDF so it is unit tested on
+// the host.
+
+// Backslash-escape the characters that are special in this part of a line
+fn escape(value: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// One line of line protocol with second precision, e.g.
+/// `temperature,sensor=28ff0102030405a6,pin=gpio4 value=21.5 1700000000`
+pub fn influx_line(measurement: &str, tags: &[(&str, &str)], value: f32, timestamp: i64) -> String {
+    let mut line = escape(measurement, &[',', ' ']);
+    for (k, v) in tags.iter().filter(|(_, v)| !v.is_empty()) {
+        line += &format!(
+            ",{}={}",
+            escape(k, &[',', '=', ' ']),
+            escape(v, &[',', '=', ' '])
+        );
+    }
+    line += &format!(" value={value} {timestamp}");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_line() {
+        let line = influx_line(
+            "temperature",
+            &[("sensor", "28ff0102030405a6"), ("pin", "gpio4")],
+            21.5,
+            1700000000,
+        );
+        assert_eq!(
+            line,
+            "temperature,sensor=28ff0102030405a6,pin=gpio4 value=21.5 1700000000"
+        );
+    }
+
+    #[test]
+    fn empty_tags_are_left_out() {
+        let line = influx_line("t", &[("alias", ""), ("unit", "C")], -3.25, 1);
+        assert_eq!(line, "t,unit=C value=-3.25 1");
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let line = influx_line(
+            "room temp,v2",
+            &[("alias", "living room, north=1"), ("my tag", "x")],
+            20.0,
+            1700000000,
+        );
+        assert_eq!(
+            line,
+            r"room\ temp\,v2,alias=living\ room\,\ north\=1,my\ tag=x value=20 1700000000"
+        );
+
+        // `=` is only special in tags, not in the measurement name
+        assert_eq!(influx_line("a=b", &[], 1.0, 0), "a=b value=1 0");
+    }
+}
+
+// EOF
@@ -23,34 +23,63 @@ pub use esp_idf_hal::{
 pub use esp_idf_svc::{nvs, sntp, wifi::WifiDriver};
 pub use serde::{Deserialize, Serialize};
 pub use tokio::{
-    sync::RwLock,
+    sync::{Mutex, RwLock},
     time::{Duration, sleep, timeout},
 };
 pub use tracing::*;
 
-mod config;
-pub use config::*;
-
-mod state;
-pub use state::*;
-
-mod measure;
-pub use measure::*;
-
-mod rmt_ow;
-pub use rmt_ow::*;
-
-mod mqtt;
-pub use mqtt::*;
-
-mod apiserver;
-pub use apiserver::*;
-
-mod esphome_api;
-pub use esphome_api::*;
+// Every module is declared here once: glob re-exported as the crate prelude, and listed by
+// its short name in `LOG_MODULES` for the per-module log levels, so none can be left out
+macro_rules! modules {
+    ($($module:ident)*) => {
+        $(
+            mod $module;
+            pub use $module::*;
+        )*
+
+        /// Modules of this crate, addressed by these short names in the per-module log levels
+        pub const LOG_MODULES: &[&str] = &[$(stringify!($module)),*];
+    };
+}
 
-mod wifi;
-pub use wifi::*;
+modules! {
+    config
+    config_store
+    logging
+    log_levels
+    syslog_format
+    state
+    measure
+    trend
+    degreedays
+    degreeday_totals
+    units
+    thermostat
+    pid
+    pwm
+    alert_rules
+    alerts
+    onewire
+    rmt_ow
+    bus_backends
+    bitbang_ow
+    ds2482
+    ow_worker
+    http_worker
+    mqtt
+    hass
+    influx
+    influx_line
+    push_template
+    push
+    graphite
+    announce
+    apiserver
+    prometheus
+    metrics
+    esphome_api
+    wifi
+}
 
 pub const FW_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const AP_MODE_SSID: &str = "esp32temp";
@@ -62,6 +91,12 @@ pub const LED_ACTIVE_LOW: bool = true;
 #[cfg(feature = "esp-wroom-32")]
 pub const LED_ACTIVE_LOW: bool = false;
 
+// 1-Wire buses that can hold their RMT TX and RX channel at the same time
+#[cfg(feature = "esp32-c3")]
+pub const RMT_ONEWIRE_BUSES: usize = 2;
+#[cfg(feature = "esp-wroom-32")]
+pub const RMT_ONEWIRE_BUSES: usize = 4;
+
 pub const NO_TEMP: f32 = -1000.0;
 
 #[derive(Clone, Debug, Serialize)]
@@ -69,6 +104,14 @@ pub struct TempData {
     pub iopin: String,
     pub sensor: String,
     pub value: f32,
+    pub interval: u64,
+    pub rate_min: Option<f32>,
+    pub rate_h: Option<f32>,
+    pub trend: Trend,
+    // unix time of the actual read, retries used and conversion duration
+    pub timestamp: i64,
+    pub retries: u32,
+    pub conversion_ms: u32,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -83,6 +126,7 @@ pub struct TempValues {
     pub last_update: String,
     pub uptime: u32,
     pub uptime_s: String,
+    pub unit: TempUnit,
     pub temperatures: Vec<TempData>,
 }
 
@@ -93,6 +137,7 @@ impl TempValues {
             last_update: "-".to_string(),
             uptime: 0,
             uptime_s: "-".to_string(),
+            unit: TempUnit::Celsius,
             temperatures: Vec::new(),
         }
     }
@@ -102,6 +147,7 @@ impl TempValues {
             last_update: "-".to_string(),
             uptime: 0,
             uptime_s: "-".to_string(),
+            unit: TempUnit::Celsius,
             temperatures: Vec::with_capacity(c),
         }
     }
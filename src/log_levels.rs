@@ -0,0 +1,111 @@
+// log_levels.rs
+//
+// Overall and per-module log levels as configured in `log_level` / `log_modules`,
+// kept free of ESP-IDF so the parser is unit tested on the host.
+
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+
+/// Log level overall and per module, from `log_level` (e.g. "info") and `log_modules`
+/// (e.g. "measure=warn, mqtt=debug")
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogLevels {
+    pub level: ::log::LevelFilter,
+    pub modules: BTreeMap<String, ::log::LevelFilter>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            level: ::log::LevelFilter::Info,
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn parse_level(level: &str) -> anyhow::Result<::log::LevelFilter> {
+    match level.trim().parse() {
+        Ok(level) => Ok(level),
+        Err(_) => bail!("invalid log level {level:?}"),
+    }
+}
+
+pub fn level_name(level: ::log::LevelFilter) -> String {
+    level.as_str().to_ascii_lowercase()
+}
+
+impl LogLevels {
+    pub fn parse(level: &str, modules: &str) -> anyhow::Result<Self> {
+        let mut levels = LogLevels {
+            level: parse_level(level)?,
+            modules: BTreeMap::new(),
+        };
+        for entry in modules.split(',').filter(|e| !e.trim().is_empty()) {
+            let Some((module, level)) = entry.split_once('=') else {
+                bail!("invalid module log level {entry:?}, expected module=level");
+            };
+            levels
+                .modules
+                .insert(module.trim().to_string(), parse_level(level)?);
+        }
+        Ok(levels)
+    }
+
+    /// The `log_level` form, e.g. "info"
+    pub fn level_string(&self) -> String {
+        level_name(self.level)
+    }
+
+    /// The `log_modules` form, e.g. "measure=warn, mqtt=debug"
+    pub fn modules_string(&self) -> String {
+        self.modules
+            .iter()
+            .map(|(module, level)| format!("{module}={}", level_name(*level)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::log::LevelFilter;
+
+    use super::*;
+
+    #[test]
+    fn overall_level_only() {
+        let levels = LogLevels::parse(" DEBUG ", "").unwrap();
+        assert_eq!(levels.level, LevelFilter::Debug);
+        assert!(levels.modules.is_empty());
+        assert_eq!(levels.level_string(), "debug");
+        assert_eq!(levels.modules_string(), "");
+    }
+
+    #[test]
+    fn module_levels() {
+        let levels = LogLevels::parse("info", "mqtt=debug, measure = Warn,,esp-tls=off").unwrap();
+        assert_eq!(levels.modules.len(), 3);
+        assert_eq!(levels.modules["measure"], LevelFilter::Warn);
+        assert_eq!(levels.modules["esp-tls"], LevelFilter::Off);
+        // sorted by module, and parsed back to the same levels
+        let modules = levels.modules_string();
+        assert_eq!(modules, "esp-tls=off, measure=warn, mqtt=debug");
+        assert_eq!(LogLevels::parse("info", &modules).unwrap(), levels);
+
+        // the last entry for a module wins
+        let levels = LogLevels::parse("info", "mqtt=debug,mqtt=error").unwrap();
+        assert_eq!(levels.modules["mqtt"], LevelFilter::Error);
+    }
+
+    #[test]
+    fn invalid_levels() {
+        assert!(LogLevels::parse("verbose", "").is_err());
+        assert!(LogLevels::parse("", "").is_err());
+        assert!(LogLevels::parse("info", "mqtt").is_err());
+        assert!(LogLevels::parse("info", "mqtt=loud").is_err());
+        assert!(LogLevels::parse("info", "mqtt=debug;measure=warn").is_err());
+    }
+}
+
+// EOF
@@ -0,0 +1,447 @@
+// logging.rs
+//
+// Global logger: everything from the `tracing` macros reaches the `log` crate through
+// tracing's log bridge, and is written to the serial console by EspLogger as before.
+// The console lines are also kept in a RAM ring buffer for `GET /log`. Optionally,
+// records are forwarded as RFC 5424 syslog over UDP or TCP. Formatting is done in the
+// caller, sending happens in `run_syslog` so that logging never blocks. Log levels can
+// be changed at runtime, overall and per module, with `GET/POST /loglevel`. The level
+// parser is in `log_levels.rs`, the syslog message format in `syslog_format.rs`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::{Mutex as StdMutex, OnceLock},
+    time::Instant,
+};
+
+// `log` alone would be tracing's hidden re-export module from the prelude
+use ::log::Log;
+
+use esp_idf_svc::log::{EspIdfLogFilter, EspLogger};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+    sync::mpsc,
+};
+
+use crate::*;
+
+// the ring buffer keeps at most this many lines and bytes
+const LOG_RING_LINES: usize = 300;
+const LOG_RING_BYTES: usize = 24 * 1024;
+const LOG_LINE_MAX: usize = 512;
+const SYSLOG_QUEUE: usize = 64;
+const SYSLOG_CONNECT_TIMEOUT_S: u64 = 5;
+const SYSLOG_RETRY_S: u64 = 10;
+
+static LOGGER: MyLogger = MyLogger {
+    console: EspLogger::new(EspIdfLogFilter::new()),
+    ring: StdMutex::new(LogRing {
+        lines: VecDeque::new(),
+        bytes: 0,
+        next_seq: 0,
+    }),
+    levels: StdMutex::new(AppliedLevels {
+        levels: LogLevels {
+            level: ::log::LevelFilter::Info,
+            modules: BTreeMap::new(),
+        },
+        targets: BTreeSet::new(),
+    }),
+    syslog: OnceLock::new(),
+    syslog_dropped: AtomicU32::new(0),
+};
+
+// Our own modules by their short name, anything else (other crates, ESP-IDF
+// component tags) as the target itself
+fn log_target(module: &str) -> String {
+    if LOG_MODULES.contains(&module) {
+        format!("{}::{module}", env!("CARGO_CRATE_NAME"))
+    } else {
+        module.to_string()
+    }
+}
+
+struct AppliedLevels {
+    levels: LogLevels,
+    // targets that have had their own level at some point
+    targets: BTreeSet<String>,
+}
+
+struct LogRing {
+    lines: VecDeque<(u64, String)>,
+    bytes: usize,
+    // sequence number of the next line
+    next_seq: u64,
+}
+
+impl LogRing {
+    fn push(&mut self, line: String) {
+        while !self.lines.is_empty()
+            && (self.lines.len() >= LOG_RING_LINES || self.bytes + line.len() > LOG_RING_BYTES)
+        {
+            if let Some((_, old)) = self.lines.pop_front() {
+                self.bytes -= old.len();
+            }
+        }
+        self.bytes += line.len();
+        self.lines.push_back((self.next_seq, line));
+        self.next_seq += 1;
+    }
+}
+
+struct SyslogSender {
+    level: ::log::LevelFilter,
+    tx: mpsc::Sender<SyslogRecord>,
+}
+
+struct MyLogger {
+    console: EspLogger,
+    ring: StdMutex<LogRing>,
+    levels: StdMutex<AppliedLevels>,
+    syslog: OnceLock<SyslogSender>,
+    // records lost because the queue was full
+    syslog_dropped: AtomicU32,
+}
+
+impl MyLogger {
+    // Same content as the console line, with wall clock time once it has been set
+    fn ring_push(&self, record: &::log::Record) {
+        let now = Local::now();
+        let time = if now.year() > 2020 {
+            now.format("%Y-%m-%d %H:%M:%S").to_string()
+        } else {
+            // SAFETY: plain getter of the milliseconds since boot
+            format!("({})", unsafe { esp_idf_sys::esp_log_timestamp() })
+        };
+        let marker = match record.level() {
+            ::log::Level::Error => "E",
+            ::log::Level::Warn => "W",
+            ::log::Level::Info => "I",
+            ::log::Level::Debug => "D",
+            ::log::Level::Trace => "V",
+        };
+        let mut line = format!("{time} {marker} {}: {}", record.target(), record.args());
+        if line.len() > LOG_LINE_MAX {
+            let mut end = LOG_LINE_MAX;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        line.push('\n');
+
+        if let Ok(mut ring) = self.ring.lock() {
+            ring.push(line);
+        }
+    }
+
+    fn syslog_enabled(&self, metadata: &::log::Metadata) -> bool {
+        // errors of the syslog task itself are not sent to syslog
+        self.syslog
+            .get()
+            .is_some_and(|s| metadata.level() <= s.level)
+            && metadata.target() != module_path!()
+    }
+}
+
+impl Log for MyLogger {
+    // the console levels apply to the ring buffer and syslog as well
+    fn enabled(&self, metadata: &::log::Metadata) -> bool {
+        self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &::log::Record) {
+        if !self.console.enabled(record.metadata()) {
+            return;
+        }
+        self.console.log(record);
+        self.ring_push(record);
+
+        if !self.syslog_enabled(record.metadata()) {
+            return;
+        }
+        let Some(syslog) = self.syslog.get() else {
+            return;
+        };
+        let rec = SyslogRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            timestamp: Utc::now(),
+            message: record.args().to_string(),
+        };
+        if syslog.tx.try_send(rec).is_err() {
+            self.syslog_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger, in place of `EspLogger::initialize_default()`
+pub fn init_logger() {
+    ::log::set_logger(&LOGGER)
+        .map(|()| LOGGER.console.filter().initialize())
+        .unwrap();
+}
+
+/// Set the log levels, the overall level also applies to ESP-IDF's own components
+/// (up to the compile-time maximum)
+pub fn apply_log_levels(new: &LogLevels) -> anyhow::Result<()> {
+    let filter = LOGGER.console.filter();
+    let Ok(mut applied) = LOGGER.levels.lock() else {
+        bail!("log levels unavailable");
+    };
+    filter.set_target_level("*", new.level)?;
+    // targets without their own level any more follow the overall level
+    for target in applied.targets.iter() {
+        filter.set_target_level(target, new.level)?;
+    }
+    let mut max_level = new.level;
+    for (module, level) in new.modules.iter() {
+        let target = log_target(module);
+        filter.set_target_level(&target, *level)?;
+        applied.targets.insert(target);
+        max_level = max_level.max(*level);
+    }
+    ::log::set_max_level(max_level);
+    applied.levels = new.clone();
+    Ok(())
+}
+
+/// The log levels in effect
+pub fn log_levels() -> LogLevels {
+    match LOGGER.levels.lock() {
+        Ok(applied) => applied.levels.clone(),
+        Err(_) => LogLevels::default(),
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LogLevelStatus {
+    pub level: String,
+    pub modules: BTreeMap<String, String>,
+    // as saved for the next boot
+    pub saved_level: String,
+    pub saved_modules: String,
+    pub known_modules: &'static [&'static str],
+}
+
+/// Runtime change of the log levels, `persist` saves them for the next boot
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LogLevelCommand {
+    pub level: Option<String>,
+    // module name to level, "default" removes the module's own level
+    pub modules: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pub persist: bool,
+}
+
+pub async fn log_level_status(state: &MyState) -> LogLevelStatus {
+    let levels = log_levels();
+    let saved = {
+        let config = state.saved_config.read().await;
+        LogLevels::parse(&config.log_level, &config.log_modules).unwrap_or_default()
+    };
+    LogLevelStatus {
+        level: levels.level_string(),
+        modules: levels
+            .modules
+            .iter()
+            .map(|(module, level)| (module.clone(), level_name(*level)))
+            .collect(),
+        saved_level: saved.level_string(),
+        saved_modules: saved.modules_string(),
+        known_modules: LOG_MODULES,
+    }
+}
+
+pub async fn set_log_level(
+    state: &MyState,
+    cmd: LogLevelCommand,
+) -> anyhow::Result<LogLevelStatus> {
+    let mut levels = log_levels();
+    if let Some(level) = cmd.level {
+        levels.level = parse_level(&level)?;
+    }
+    for (module, level) in cmd.modules.unwrap_or_default() {
+        let module = module.trim();
+        if module.is_empty() || module.contains(char::is_whitespace) {
+            bail!("invalid module name {module:?}");
+        }
+        if level.trim().eq_ignore_ascii_case("default") {
+            levels.modules.remove(module);
+        } else {
+            levels
+                .modules
+                .insert(module.to_string(), parse_level(&level)?);
+        }
+    }
+    apply_log_levels(&levels)?;
+    info!(
+        "Log level set to {}, modules: {}",
+        level_name(levels.level),
+        levels.modules_string()
+    );
+
+    if cmd.persist {
+        state
+            .save_runtime_config(|config| {
+                config.log_level = levels.level_string();
+                config.log_modules = levels.modules_string();
+            })
+            .await?;
+    }
+    Ok(log_level_status(state).await)
+}
+
+/// Up to `max` of the buffered log lines from sequence number `since` on, oldest first,
+/// and the sequence number to continue from
+pub fn log_lines(since: u64, max: usize) -> (String, u64) {
+    let Ok(ring) = LOGGER.ring.lock() else {
+        return (String::new(), since);
+    };
+    let lines = ring
+        .lines
+        .iter()
+        .filter(|(seq, _)| *seq >= since)
+        .collect::<Vec<_>>();
+    let text = lines[lines.len().saturating_sub(max)..]
+        .iter()
+        .map(|(_, line)| line.as_str())
+        .collect();
+    (text, ring.next_seq)
+}
+
+enum SyslogConn {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl SyslogConn {
+    async fn connect(config: &MyConfig) -> anyhow::Result<Self> {
+        let server = (config.syslog_server.trim(), config.syslog_port);
+        if config.syslog_tcp {
+            match timeout(
+                Duration::from_secs(SYSLOG_CONNECT_TIMEOUT_S),
+                TcpStream::connect(server),
+            )
+            .await
+            {
+                Ok(stream) => Ok(SyslogConn::Tcp(stream?)),
+                Err(_) => bail!("connect timed out"),
+            }
+        } else {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(server).await?;
+            Ok(SyslogConn::Udp(socket))
+        }
+    }
+
+    async fn send(&mut self, msg: &str) -> std::io::Result<()> {
+        match self {
+            SyslogConn::Udp(socket) => socket.send(msg.as_bytes()).await.map(drop),
+            SyslogConn::Tcp(stream) => stream.write_all(syslog_tcp_frame(msg).as_bytes()).await,
+        }
+    }
+}
+
+pub async fn run_syslog(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    let config = &state.config;
+    if state.ap_mode || !config.syslog_enable || config.syslog_server.trim().is_empty() {
+        info!("Syslog is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let level = match config.syslog_level.trim().parse::<::log::LevelFilter>() {
+        Ok(level) => level,
+        Err(_) => {
+            error!("Invalid syslog level {}, using info", config.syslog_level);
+            ::log::LevelFilter::Info
+        }
+    };
+    let (tx, mut rx) = mpsc::channel(SYSLOG_QUEUE);
+    if LOGGER.syslog.set(SyslogSender { level, tx }).is_err() {
+        bail!("syslog already started");
+    }
+    info!(
+        "Syslog to {}:{} ({}), level {level}",
+        config.syslog_server,
+        config.syslog_port,
+        if config.syslog_tcp { "TCP" } else { "UDP" }
+    );
+
+    // records logged until then wait in the queue
+    while !*state.wifi_up.read().await {
+        sleep(Duration::from_secs(1)).await;
+    }
+
+    // token bucket, at most `syslog_rate` messages per second
+    let rate = config.syslog_rate.max(1) as f32;
+    let mut tokens = rate;
+    let mut last_refill = Instant::now();
+    // records not sent, reported with the next one that is
+    let mut lost = 0;
+
+    let mut conn: Option<SyslogConn> = None;
+    let mut retry_at: Option<Instant> = None;
+    while let Some(rec) = rx.recv().await {
+        lost += LOGGER.syslog_dropped.swap(0, Ordering::Relaxed);
+        let now = Instant::now();
+        tokens = (tokens + now.duration_since(last_refill).as_secs_f32() * rate).min(rate);
+        last_refill = now;
+        if tokens < 1.0 {
+            lost += 1;
+            continue;
+        }
+        tokens -= 1.0;
+
+        if conn.is_none() && retry_at.is_none_or(|at| now >= at) {
+            match SyslogConn::connect(config).await {
+                Ok(c) => conn = Some(c),
+                Err(e) => {
+                    error!("Syslog connect error: {e:#}");
+                    retry_at = Some(now + Duration::from_secs(SYSLOG_RETRY_S));
+                }
+            }
+        }
+        let Some(c) = conn.as_mut() else {
+            lost += 1;
+            continue;
+        };
+
+        let hostname = state.myid.read().await.clone();
+        let mut messages = Vec::with_capacity(2);
+        if lost > 0 {
+            messages.push(syslog_message(
+                &SyslogRecord {
+                    level: ::log::Level::Warn,
+                    target: module_path!().to_string(),
+                    timestamp: Utc::now(),
+                    message: format!("{lost} message(s) dropped"),
+                },
+                &hostname,
+            ));
+        }
+        messages.push(syslog_message(&rec, &hostname));
+
+        for msg in messages.iter() {
+            if let Err(e) = c.send(msg).await {
+                error!("Syslog send error: {e}");
+                conn = None;
+                retry_at = Some(now + Duration::from_secs(SYSLOG_RETRY_S));
+                break;
+            }
+        }
+        if conn.is_some() {
+            lost = 0;
+        } else {
+            lost += 1;
+        }
+    }
+    bail!("syslog queue closed")
+}
+
+// EOF
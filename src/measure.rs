@@ -1,8 +1,15 @@
 // measure.rs
 
+use std::{collections::BTreeMap, time::Instant};
+
+use futures_util::future::join_all;
+use tokio::sync::{Semaphore, oneshot};
+
 use crate::*;
 
 const DS18B20_FAMILY_CODE: u8 = 0x28;
+const CONVERSION_POLL_MS: u64 = 10;
+const CONVERSION_TIMEOUT_MARGIN_MS: u64 = 100;
 
 #[repr(u8)]
 enum Ds18b20Command {
@@ -29,8 +36,119 @@ impl MeasureResolution {
 pub struct Measurement {
     pub device_id: String,
     pub temperature: f32,
+    pub timestamp: i64,
+    pub retries: u32,
+    pub conversion_ms: u32,
+}
+
+/// Per-sensor counters, shown by `GET /sensors/{rom_id}`
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SensorStats {
+    pub reads: u32,
+    pub read_errors: u32,
+    pub crc_errors: u32,
+    pub failed_measurements: u32,
+    // measurements failed in a row, 0 after a successful one
+    pub consecutive_failures: u32,
+    // unix time of the last successful measurement
+    pub last_success: Option<i64>,
+    // why the last failed measurement failed
+    pub last_error: Option<String>,
+    pub parasite: Option<bool>,
+}
+
+pub type SensorStatsMap = RwLock<BTreeMap<String, SensorStats>>;
+
+/// Tracks what a push output has already sent, sensors may be on different poll intervals
+#[derive(Debug, Default)]
+pub struct NewReadings {
+    last_cycle: i64,
+    sent: BTreeMap<String, i64>,
+}
+
+impl NewReadings {
+    /// Cycle time and the readings taken since the previous call, None if no poll cycle
+    /// has completed in between
+    pub async fn take(&mut self, state: &MyState) -> Option<(i64, Vec<TempData>)> {
+        let data = state.data.read().await;
+        if data.timestamp == self.last_cycle {
+            return None;
+        }
+        self.last_cycle = data.timestamp;
+
+        let fresh = data
+            .temperatures
+            .iter()
+            .filter(|t| {
+                t.value > NO_TEMP
+                    && !self
+                        .sent
+                        .get(&t.sensor)
+                        .is_some_and(|ts| *ts >= t.timestamp)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for t in fresh.iter() {
+            self.sent.insert(t.sensor.clone(), t.timestamp);
+        }
+        Some((data.timestamp, fresh))
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ScratchpadInfo {
+    pub raw: String,
+    pub temperature: f32,
+    pub th: i8,
+    pub tl: i8,
+    pub config: u8,
+    pub resolution_bits: u8,
+    pub crc: u8,
+    pub crc_computed: u8,
+    pub crc_ok: bool,
+}
+
+impl ScratchpadInfo {
+    pub fn decode(scratchpad: &[u8; 9]) -> Self {
+        let crc_computed = compute_crc8(&scratchpad[..8]);
+        Self {
+            raw: scratchpad
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<String>>()
+                .join(" "),
+            temperature: f32::from(i16::from_le_bytes([scratchpad[0], scratchpad[1]])) / 16.0,
+            th: scratchpad[2] as i8,
+            tl: scratchpad[3] as i8,
+            config: scratchpad[4],
+            // R1:R0 in bits 6:5 select 9..12 bits
+            resolution_bits: 9 + ((scratchpad[4] >> 5) & 0x03),
+            crc: scratchpad[8],
+            crc_computed,
+            crc_ok: crc_computed == scratchpad[8],
+        }
+    }
 }
 
+#[derive(Debug)]
+struct ScratchpadCrcError {
+    device_id: String,
+    computed: u8,
+    expected: u8,
+}
+
+impl std::fmt::Display for ScratchpadCrcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Scratchpad CRC mismatch for {}: computed=0x{:02X} expected=0x{:02X}",
+            self.device_id, self.computed, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ScratchpadCrcError {}
+
 #[derive(Debug)]
 pub struct ScanResult {
     pub all_devices: Vec<OWAddress>,
@@ -44,61 +162,180 @@ pub fn format_device_id(device: &OWAddress) -> String {
     )
 }
 
+// A sensor that fails is counted and skipped, the others on the bus are still measured
 pub async fn measure_temperatures(
-    one_wire_bus: &OWDriver<'_>,
+    one_wire_bus: &OWSession<'_>,
     devices: &[OWAddress],
     max_retry: u32,
-) -> anyhow::Result<Vec<Measurement>> {
+    stats: &SensorStatsMap,
+) -> Vec<Measurement> {
     let mut meas = Vec::new();
 
     for device in devices.iter() {
         let device_id = format_device_id(device);
-        set_resolution(one_wire_bus, device, MeasureResolution::TC)?;
+        match Box::pin(measure_device(
+            one_wire_bus,
+            device,
+            &device_id,
+            max_retry,
+            stats,
+        ))
+        .await
+        {
+            Ok(m) => {
+                info!("Got meas, retry#{}: {m:?}", m.retries);
+                let mut stats = stats.write().await;
+                let s = stats.entry(device_id).or_default();
+                s.reads += 1;
+                s.consecutive_failures = 0;
+                s.last_success = Some(m.timestamp);
+                meas.push(m);
+            }
+            Err(e) => {
+                error!("Sensor {device_id} measurement failed: {e:#}");
+                record_failure(stats, &device_id, &e).await;
+            }
+        }
 
-        sleep(Duration::from_millis(50)).await;
-        let wait_ms = start_temperature_measurement(one_wire_bus, device, MeasureResolution::TC)?;
+        sleep(Duration::from_millis(100)).await;
+    }
+    meas
+}
+
+async fn record_failure(stats: &SensorStatsMap, device_id: &str, error: &anyhow::Error) {
+    let mut stats = stats.write().await;
+    let s = stats.entry(device_id.to_string()).or_default();
+    s.failed_measurements += 1;
+    s.consecutive_failures += 1;
+    s.last_error = Some(format!("{error:#}"));
+    // a probe may have been rewired, its power mode is checked again next time
+    s.parasite = None;
+}
+
+// Read Power Supply for one device and cache the result per ROM id
+async fn power_supply(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+    device_id: &str,
+    stats: &SensorStatsMap,
+) -> anyhow::Result<bool> {
+    let parasite = Box::pin(read_power_supply(one_wire_bus, device)).await?;
+    info!(
+        "Sensor {device_id} is {}",
+        if parasite {
+            "parasite-powered"
+        } else {
+            "externally powered"
+        }
+    );
+    if parasite && !one_wire_bus.has_strong_pullup() {
+        warn!(
+            "Sensor {device_id} is parasite-powered on a bus without strong pull-up, \
+             it may read 85 °C or fail CRC checks: power it externally or use a DS2482 bus"
+        );
+    }
+    stats
+        .write()
+        .await
+        .entry(device_id.to_string())
+        .or_default()
+        .parasite = Some(parasite);
+    Ok(parasite)
+}
+
+// Power mode of every sensor found at boot, so that measurements do not need to ask.
+// Sensors that do not answer here are checked on their first measurement.
+async fn detect_power_supply(state: &MyState) {
+    let onewires = state.sensors.read().await;
+    for onew in onewires.iter() {
+        let bus = onew.bus.lock().await;
+        let w = match bus.open().await {
+            Ok(w) => w,
+            Err(e) => {
+                error!("Power mode check on {} failed: {e:#}", onew.name);
+                continue;
+            }
+        };
+        for device in onew.ids.iter() {
+            let device_id = format_device_id(device);
+            if let Err(e) =
+                Box::pin(power_supply(&w, device, &device_id, &state.sensor_stats)).await
+            {
+                error!("Sensor {device_id} power mode check failed: {e:#}");
+            }
+        }
+    }
+}
+
+async fn measure_device(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+    device_id: &str,
+    max_retry: u32,
+    stats: &SensorStatsMap,
+) -> anyhow::Result<Measurement> {
+    Box::pin(set_resolution(one_wire_bus, device, MeasureResolution::TC)).await?;
+
+    let cached = stats.read().await.get(device_id).and_then(|s| s.parasite);
+    let parasite = match cached {
+        Some(parasite) => parasite,
+        None => Box::pin(power_supply(one_wire_bus, device, device_id, stats)).await?,
+    };
+
+    sleep(Duration::from_millis(50)).await;
+    let conversion_start = Instant::now();
+    let wait_ms = Box::pin(start_temperature_measurement(
+        one_wire_bus,
+        device,
+        MeasureResolution::TC,
+        parasite,
+    ))
+    .await?;
+    if parasite {
+        // the strong pull-up is holding the bus, so no read slots until it is released
         sleep(Duration::from_millis(u64::from(wait_ms))).await;
-        sleep(Duration::from_millis(10)).await;
+        one_wire_bus.strong_pullup(false).await?;
+    } else {
+        Box::pin(wait_conversion(one_wire_bus, device_id, wait_ms)).await?;
+    }
+    let conversion_ms = conversion_start.elapsed().as_millis() as u32;
+    sleep(Duration::from_millis(10)).await;
 
-        let mut retries = 0;
-        loop {
-            match read_temperature(one_wire_bus, device) {
-                Ok(temperature) => {
-                    let m = Measurement {
-                        device_id: device_id.clone(),
-                        temperature,
-                    };
-                    info!("Got meas, retry#{retries}: {m:?}");
-                    meas.push(m);
-                    break;
+    let mut retries = 0;
+    loop {
+        match Box::pin(read_temperature(one_wire_bus, device)).await {
+            Ok(temperature) => {
+                return Ok(Measurement {
+                    device_id: device_id.to_string(),
+                    temperature,
+                    timestamp: Utc::now().timestamp(),
+                    retries,
+                    conversion_ms,
+                });
+            }
+            Err(e) => {
+                retries += 1;
+                error!("Sensor {device_id} read error: {e:#}");
+                let mut stats = stats.write().await;
+                let s = stats.entry(device_id.to_string()).or_default();
+                s.read_errors += 1;
+                if e.is::<ScratchpadCrcError>() {
+                    s.crc_errors += 1;
                 }
-                Err(e) => {
-                    retries += 1;
-                    error!("Sensor {device_id} read error: {e:#}");
-                    if retries > max_retry {
-                        break;
-                    }
+                if retries > max_retry {
+                    bail!("no valid reading after {retries} attempts");
                 }
             }
-            sleep(Duration::from_millis(100)).await;
         }
-
         sleep(Duration::from_millis(100)).await;
     }
-
-    if meas.is_empty() {
-        bail!("No DS18B20 measurements succeeded");
-    } else {
-        Ok(meas)
-    }
 }
 
-pub fn scan_1wire(one_wire_bus: &mut OWDriver<'_>) -> anyhow::Result<ScanResult> {
+pub fn scan_1wire(one_wire_bus: &mut dyn OneWireBus) -> anyhow::Result<ScanResult> {
     let mut all_devices = Vec::new();
     let mut ds18b20_devices = Vec::new();
 
-    for device in one_wire_bus.search()? {
-        let device = device?;
+    for device in one_wire_bus.search_devices()? {
         if device.family_code() == DS18B20_FAMILY_CODE {
             ds18b20_devices.push(device);
         }
@@ -151,75 +388,391 @@ pub async fn poll_sensors(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
     *state.ntp_ok.write().await = true;
     info!("NTP ok.");
 
-    let poll_delay = state.config.delay;
+    Box::pin(detect_power_supply(&state)).await;
+
     let max_retry = state.config.retries;
+    let bus_groups = group_buses(&state).await;
+    let (offsets, labels) = {
+        let onewires = state.sensors.read().await;
+        (bus_offsets(&onewires), sensor_labels(&onewires))
+    };
+    let mut schedule = vec![SensorSchedule::new(state.config.delay); labels.len()];
+    let mut history = vec![SensorHistory::new(state.config.trend_samples as usize); labels.len()];
+    let Some(mut measure_rx) = state.measure_rx.lock().await.take() else {
+        bail!("Measurement request channel already taken");
+    };
+    *state.poller_ready.write().await = true;
+    // RMT buses beyond this wait for a channel instead of falling back to bit-banging
+    let rmt_buses = Semaphore::new(RMT_ONEWIRE_BUSES);
+    let mut request: Option<MeasureRequest> = None;
     loop {
-        info!("Polling 1-wire sensors");
-        state.led_on().await?;
+        let now = Instant::now();
+        let mut due = schedule
+            .iter()
+            .map(|s| s.next_due <= now)
+            .collect::<Vec<bool>>();
+        // sensors requested through POST /measure are measured right away
+        let requested = labels
+            .iter()
+            .map(|(pin, id)| request.as_ref().is_some_and(|r| r.scope.matches(pin, id)))
+            .collect::<Vec<bool>>();
+        due.iter_mut()
+            .zip(requested.iter())
+            .for_each(|(d, r)| *d |= *r);
+        let mut measured = vec![false; due.len()];
+
+        if due.iter().any(|d| *d) {
+            info!("Polling 1-wire sensors");
+            state.led_on().await?;
+
+            // Only a read lock: /sensors and ESPHome can list sensors while we measure
+            let onewires = state.sensors.read().await;
+            let results = join_all(bus_groups.iter().map(|group| {
+                Box::pin(measure_group(
+                    &onewires,
+                    group,
+                    &offsets,
+                    &due,
+                    max_retry,
+                    &state.sensor_stats,
+                    &rmt_buses,
+                ))
+            }))
+            .await;
 
-        {
-            let mut onewires = state.sensors.write().await;
-            let mut i = 0;
-            for onew in onewires.iter_mut() {
-                let w = OWDriver::new(unsafe { onew.pin.reborrow() })?;
-                match Box::pin(measure_temperatures(&w, &onew.ids, max_retry)).await {
-                    Ok(meas) => {
-                        info!("Onewire response {name}:\n{meas:#?}", name = onew.name);
-                        let mut data = state.data.write().await;
-                        for m in meas.into_iter() {
-                            data.temperatures[i] = TempData {
-                                iopin: onew.name.clone(),
-                                sensor: m.device_id,
-                                value: m.temperature,
-                            };
-                            i += 1;
-                        }
-                    }
-                    Err(e) => {
-                        error!("Temp read error: {e:#}");
-                        break;
-                    }
+            let mut data = state.data.write().await;
+            for (idx, meas) in results.into_iter().flatten() {
+                let onew = &onewires[idx];
+                for m in meas.into_iter() {
+                    let Some(pos) = onew
+                        .ids
+                        .iter()
+                        .position(|id| format_device_id(id) == m.device_id)
+                    else {
+                        continue;
+                    };
+                    let i = offsets[idx] + pos;
+                    history[i].push(Instant::now(), m.temperature);
+                    let rate_min = history[i].rate_per_min();
+                    let interval = schedule[i].update(&state.config, rate_min);
+                    update_degree_days(&state, &m.device_id, m.temperature).await;
+                    data.temperatures[i] = TempData {
+                        iopin: onew.name.clone(),
+                        sensor: m.device_id,
+                        value: m.temperature,
+                        interval,
+                        rate_min,
+                        rate_h: rate_min.map(|r| r * 60.0),
+                        trend: Trend::from_rate(rate_min, state.config.trend_band),
+                        timestamp: m.timestamp,
+                        retries: m.retries,
+                        conversion_ms: m.conversion_ms,
+                    };
+                    measured[i] = true;
                 }
-                drop(w);
-                sleep(Duration::from_millis(100)).await;
             }
-            let mut data = state.data.write().await;
+
+            // failed sensors are simply retried on their current interval
+            for (s, _) in schedule.iter_mut().zip(due.iter()).filter(|(_, d)| **d) {
+                s.reschedule(now);
+            }
+
             let now = Utc::now();
             data.timestamp = now.timestamp();
             data.last_update = now.to_rfc2822().to_string();
             let mut fresh_data = state.fresh_data.write().await;
             *fresh_data = true;
+            drop(data);
+            drop(onewires);
+
+            state.led_off().await?;
+        }
+
+        if let Some(req) = request.take() {
+            let reply = if requested.iter().any(|r| *r) {
+                let data = state.data.read().await;
+                let stats = state.sensor_stats.read().await;
+                let mut reply = MeasureReply::default();
+                for (i, (pin, id)) in labels.iter().enumerate().filter(|(i, _)| requested[*i]) {
+                    if measured[i] {
+                        reply.temperatures.push(data.temperatures[i].clone());
+                    } else {
+                        reply.failed.push(MeasureFailure {
+                            iopin: pin.clone(),
+                            sensor: id.clone(),
+                            error: stats
+                                .get(id)
+                                .and_then(|s| s.last_error.clone())
+                                .unwrap_or_default(),
+                        });
+                    }
+                }
+                Some(reply)
+            } else {
+                None
+            };
+            // the requester may have timed out
+            let _ = req.resp.send(reply);
+        }
+
+        let next_due = schedule
+            .iter()
+            .map(|s| s.next_due)
+            .min()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(state.config.delay));
+        let wait = next_due
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_secs(1));
+        tokio::select! {
+            _ = sleep(wait) => {}
+            req = measure_rx.recv() => { request = req; }
         }
-        state.led_off().await?;
+    }
+}
 
-        sleep(Duration::from_secs(poll_delay)).await;
+/// Which sensors a `POST /measure` request covers
+#[derive(Clone, Debug)]
+pub enum MeasureScope {
+    All,
+    Pin(String),
+    Sensor(String),
+}
+
+impl MeasureScope {
+    fn matches(&self, pin: &str, sensor: &str) -> bool {
+        match self {
+            MeasureScope::All => true,
+            MeasureScope::Pin(p) => p.eq_ignore_ascii_case(pin),
+            MeasureScope::Sensor(s) => s.eq_ignore_ascii_case(sensor),
+        }
     }
 }
 
-fn start_temperature_measurement(
-    one_wire_bus: &OWDriver<'_>,
+/// A requested sensor that could not be measured
+#[derive(Clone, Debug, Serialize)]
+pub struct MeasureFailure {
+    pub iopin: String,
+    pub sensor: String,
+    pub error: String,
+}
+
+/// The requested sensors, split into the ones measured successfully and the failed ones
+#[derive(Clone, Debug, Default)]
+pub struct MeasureReply {
+    pub temperatures: Vec<TempData>,
+    pub failed: Vec<MeasureFailure>,
+}
+
+/// On-demand measurement, answered with `None` when the scope matches no sensor
+pub struct MeasureRequest {
+    pub scope: MeasureScope,
+    pub resp: oneshot::Sender<Option<MeasureReply>>,
+}
+
+// Per-sensor adaptive polling: sample at `delay_fast` while the temperature changes faster
+// than `rate_threshold` degrees per minute, otherwise back off to `delay`. The rate is the
+// sensor's trend (`trend.rs`), so a single noisy reading does not switch the interval.
+#[derive(Clone, Debug)]
+struct SensorSchedule {
+    interval: u64,
+    next_due: Instant,
+}
+
+impl SensorSchedule {
+    fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            next_due: Instant::now(),
+        }
+    }
+
+    // the interval stays as it is until the trend has a rate
+    fn update(&mut self, config: &MyConfig, rate_min: Option<f32>) -> u64 {
+        if let Some(rate) = rate_min {
+            self.interval = if rate.abs() >= config.rate_threshold {
+                config.delay_fast.min(config.delay)
+            } else {
+                config.delay
+            };
+        }
+        self.interval
+    }
+
+    fn reschedule(&mut self, started: Instant) {
+        self.next_due = started + Duration::from_secs(self.interval);
+    }
+}
+
+// Buses are measured concurrently, except the ones sharing hardware (DS2482 channels),
+// which are measured one after another within the same group. The bus operations
+// themselves are still run one at a time by the 1-Wire worker, what overlaps are
+// the conversion waits.
+async fn group_buses(state: &MyState) -> Vec<Vec<usize>> {
+    let onewires = state.sensors.read().await;
+    let mut groups: Vec<(u32, Vec<usize>)> = Vec::new();
+    for (idx, onew) in onewires.iter().enumerate() {
+        let hw = onew.bus.lock().await.hw_group();
+        match groups.iter_mut().find(|(g, _)| *g == hw) {
+            Some((_, members)) => members.push(idx),
+            None => groups.push((hw, vec![idx])),
+        }
+    }
+    groups.into_iter().map(|(_, members)| members).collect()
+}
+
+// (bus name, sensor id) for each position in `TempValues.temperatures`
+fn sensor_labels(onewires: &[MyOnewire]) -> Vec<(String, String)> {
+    onewires
+        .iter()
+        .flat_map(|onew| {
+            onew.ids
+                .iter()
+                .map(|id| (onew.name.clone(), format_device_id(id)))
+        })
+        .collect()
+}
+
+// Index of the first sensor of each bus in the flat `TempValues.temperatures` list
+fn bus_offsets(onewires: &[MyOnewire]) -> Vec<usize> {
+    onewires
+        .iter()
+        .scan(0, |offset, onew| {
+            let start = *offset;
+            *offset += onew.ids.len();
+            Some(start)
+        })
+        .collect()
+}
+
+async fn measure_group(
+    onewires: &[MyOnewire],
+    group: &[usize],
+    offsets: &[usize],
+    due: &[bool],
+    max_retry: u32,
+    stats: &SensorStatsMap,
+    rmt_buses: &Semaphore,
+) -> Vec<(usize, Vec<Measurement>)> {
+    let mut results = Vec::with_capacity(group.len());
+    for idx in group.iter().copied() {
+        let onew = &onewires[idx];
+        let devices = onew
+            .ids
+            .iter()
+            .enumerate()
+            .filter(|(pos, _)| due[offsets[idx] + pos])
+            .map(|(_, id)| *id)
+            .collect::<Vec<OWAddress>>();
+        if devices.is_empty() {
+            continue;
+        }
+
+        match Box::pin(measure_bus(onew, &devices, max_retry, stats, rmt_buses)).await {
+            Ok(meas) => {
+                info!("Onewire response {name}:\n{meas:#?}", name = onew.name);
+                results.push((idx, meas));
+            }
+            Err(e) => {
+                error!("Temp read error {name}: {e:#}", name = onew.name);
+                // none of the bus's sensors could be measured
+                for device in devices.iter() {
+                    record_failure(stats, &format_device_id(device), &e).await;
+                }
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    results
+}
+
+async fn measure_bus(
+    onew: &MyOnewire,
+    devices: &[OWAddress],
+    max_retry: u32,
+    stats: &SensorStatsMap,
+    rmt_buses: &Semaphore,
+) -> anyhow::Result<Vec<Measurement>> {
+    let bus = onew.bus.lock().await;
+    let _rmt = if bus.uses_rmt() {
+        Some(rmt_buses.acquire().await?)
+    } else {
+        None
+    };
+    let w = bus.open().await?;
+    Ok(Box::pin(measure_temperatures(&w, devices, max_retry, stats)).await)
+}
+
+async fn start_temperature_measurement(
+    one_wire_bus: &OWSession<'_>,
     device: &OWAddress,
     resolution: MeasureResolution,
+    parasite: bool,
 ) -> anyhow::Result<u16> {
-    one_wire_bus.reset()?;
-    send_command(one_wire_bus, device, Ds18b20Command::ConvertTemp as u8)?;
+    one_wire_bus.reset().await?;
+    if parasite {
+        // parasite-powered probes draw their conversion current through the strong pull-up
+        one_wire_bus.strong_pullup(true).await?;
+    }
+    send_command(one_wire_bus, device, Ds18b20Command::ConvertTemp as u8).await?;
     Ok(resolution.time_ms())
 }
 
-fn read_temperature(one_wire_bus: &OWDriver<'_>, device: &OWAddress) -> anyhow::Result<f32> {
-    let scratchpad = read_scratchpad(one_wire_bus, device)?;
+// An externally powered DS18B20 answers read slots with 0 while converting and 1 when done,
+// which is usually well before the worst case conversion time.
+async fn wait_conversion(
+    one_wire_bus: &OWSession<'_>,
+    device_id: &str,
+    max_ms: u16,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let max_wait = Duration::from_millis(u64::from(max_ms) + CONVERSION_TIMEOUT_MARGIN_MS);
+    loop {
+        sleep(Duration::from_millis(CONVERSION_POLL_MS)).await;
+
+        let mut slots = [0u8; 1];
+        one_wire_bus.read(&mut slots).await?;
+        if slots[0] != 0 {
+            debug!(
+                "Sensor {device_id} conversion done in {:?}",
+                started.elapsed()
+            );
+            return Ok(());
+        }
+
+        if started.elapsed() > max_wait {
+            warn!("Sensor {device_id} conversion not signalled complete in {max_wait:?}");
+            return Ok(());
+        }
+    }
+}
+
+// Parasite-powered devices pull the read slot low after Read Power Supply
+async fn read_power_supply(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+) -> anyhow::Result<bool> {
+    one_wire_bus.reset().await?;
+    send_command(one_wire_bus, device, OWCommand::ReadPowerSupply as u8).await?;
+
+    let mut slots = [0u8; 1];
+    one_wire_bus.read(&mut slots).await?;
+    Ok(slots[0] & 0x01 == 0)
+}
+
+async fn read_temperature(one_wire_bus: &OWSession<'_>, device: &OWAddress) -> anyhow::Result<f32> {
+    let scratchpad = Box::pin(read_scratchpad(one_wire_bus, device)).await?;
     let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]);
     Ok(f32::from(raw) / 16.0)
 }
 
-fn set_resolution(
-    one_wire_bus: &OWDriver<'_>,
+async fn set_resolution(
+    one_wire_bus: &OWSession<'_>,
     device: &OWAddress,
     resolution: MeasureResolution,
 ) -> anyhow::Result<()> {
-    let scratchpad = read_scratchpad(one_wire_bus, device)?;
+    let scratchpad = Box::pin(read_scratchpad(one_wire_bus, device)).await?;
 
-    one_wire_bus.reset()?;
+    one_wire_bus.reset().await?;
     send_bytes(
         one_wire_bus,
         device,
@@ -229,35 +782,69 @@ fn set_resolution(
             scratchpad[3],
             resolution as u8,
         ],
-    )?;
+    )
+    .await?;
 
     Ok(())
 }
 
-fn read_scratchpad(one_wire_bus: &OWDriver<'_>, device: &OWAddress) -> anyhow::Result<[u8; 9]> {
-    one_wire_bus.reset()?;
-    send_command(one_wire_bus, device, Ds18b20Command::ReadScratchpad as u8)?;
-
-    let mut scratchpad = [0u8; 9];
-    one_wire_bus.read(&mut scratchpad)?;
+async fn read_scratchpad(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+) -> anyhow::Result<[u8; 9]> {
+    let scratchpad = Box::pin(read_scratchpad_raw(one_wire_bus, device)).await?;
 
     let computed = compute_crc8(&scratchpad[..8]);
     if computed != scratchpad[8] {
-        bail!(
-            "Scratchpad CRC mismatch for {}: computed=0x{computed:02X} expected=0x{:02X}",
-            format_device_id(device),
-            scratchpad[8]
-        );
+        return Err(ScratchpadCrcError {
+            device_id: format_device_id(device),
+            computed,
+            expected: scratchpad[8],
+        }
+        .into());
     }
 
     Ok(scratchpad)
 }
 
-fn send_command(one_wire_bus: &OWDriver<'_>, device: &OWAddress, cmd: u8) -> anyhow::Result<()> {
-    send_bytes(one_wire_bus, device, &[cmd])
+// Scratchpad as read from the bus, without CRC validation
+async fn read_scratchpad_raw(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+) -> anyhow::Result<[u8; 9]> {
+    one_wire_bus.reset().await?;
+    send_command(one_wire_bus, device, Ds18b20Command::ReadScratchpad as u8).await?;
+
+    let mut scratchpad = [0u8; 9];
+    one_wire_bus.read(&mut scratchpad).await?;
+    Ok(scratchpad)
+}
+
+/// Read and decode the scratchpad and power mode of one sensor, for diagnostics.
+pub async fn inspect_sensor(
+    onew: &MyOnewire,
+    device: &OWAddress,
+) -> anyhow::Result<(ScratchpadInfo, bool)> {
+    let bus = onew.bus.lock().await;
+    let w = bus.open().await?;
+    let scratchpad = Box::pin(read_scratchpad_raw(&w, device)).await?;
+    let parasite = Box::pin(read_power_supply(&w, device)).await?;
+    Ok((ScratchpadInfo::decode(&scratchpad), parasite))
+}
+
+async fn send_command(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+    cmd: u8,
+) -> anyhow::Result<()> {
+    send_bytes(one_wire_bus, device, &[cmd]).await
 }
 
-fn send_bytes(one_wire_bus: &OWDriver<'_>, device: &OWAddress, bytes: &[u8]) -> anyhow::Result<()> {
+async fn send_bytes(
+    one_wire_bus: &OWSession<'_>,
+    device: &OWAddress,
+    bytes: &[u8],
+) -> anyhow::Result<()> {
     let mut buf = [0u8; 16];
     let addr = device.address().to_le_bytes();
 
@@ -265,7 +852,7 @@ fn send_bytes(one_wire_bus: &OWDriver<'_>, device: &OWAddress, bytes: &[u8]) ->
     buf[1..9].copy_from_slice(&addr);
     buf[9..9 + bytes.len()].copy_from_slice(bytes);
 
-    one_wire_bus.write(&buf[..9 + bytes.len()])?;
+    one_wire_bus.write(&buf[..9 + bytes.len()]).await?;
     Ok(())
 }
 
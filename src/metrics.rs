@@ -0,0 +1,101 @@
+// metrics.rs
+//
+// Prometheus text exposition format for `GET /metrics`. Temperatures are always
+// exported in Celsius, Prometheus expects base units regardless of the display unit.
+
+use crate::*;
+
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// RSSI of the associated access point, None when not connected
+fn wifi_rssi() -> Option<i8> {
+    let mut ap_info = esp_idf_sys::wifi_ap_record_t::default();
+    // SAFETY: plain query of the WiFi driver into a local struct
+    let err = unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut ap_info) };
+    (err == esp_idf_sys::ESP_OK).then_some(ap_info.rssi)
+}
+
+pub async fn render_metrics(state: &MyState) -> String {
+    let mut m = MetricsWriter::with_capacity(4096);
+
+    m.header("info", "gauge", "Firmware version and running OTA slot.");
+    m.sample(
+        "info",
+        &[("version", FW_VERSION), ("ota_slot", &state.ota_slot)],
+        1,
+    );
+
+    let data = state.data.read().await.clone();
+    m.header("uptime_seconds", "gauge", "Time since boot.");
+    m.sample("uptime_seconds", &[], data.uptime);
+
+    let rssi = if !state.ap_mode && *state.wifi_up.read().await {
+        wifi_rssi()
+    } else {
+        None
+    };
+    if let Some(rssi) = rssi {
+        m.header("wifi_rssi_dbm", "gauge", "WiFi signal strength.");
+        m.sample("wifi_rssi_dbm", &[], rssi);
+    }
+
+    // SAFETY: heap statistics getters without side effects
+    let (free_heap, min_free_heap) = unsafe {
+        (
+            esp_idf_sys::esp_get_free_heap_size(),
+            esp_idf_sys::esp_get_minimum_free_heap_size(),
+        )
+    };
+    m.header("free_heap_bytes", "gauge", "Free heap memory.");
+    m.sample("free_heap_bytes", &[], free_heap);
+    m.header(
+        "min_free_heap_bytes",
+        "gauge",
+        "Lowest free heap memory since boot.",
+    );
+    m.sample("min_free_heap_bytes", &[], min_free_heap);
+
+    m.header("api_requests_total", "counter", "HTTP API requests served.");
+    m.sample(
+        "api_requests_total",
+        &[],
+        state.api_cnt.load(Ordering::Relaxed),
+    );
+
+    // every sensor found at boot, labelled by its ROM id, bus and alias
+    let onewires = state.sensors.read().await;
+    let ids = onewires
+        .iter()
+        .flat_map(|onew| {
+            onew.ids
+                .iter()
+                .map(|id| (format_device_id(id), onew.name.as_str()))
+        })
+        .collect::<Vec<_>>();
+    let stats = state.sensor_stats.read().await.clone();
+    let series = ids
+        .iter()
+        .map(|(sensor, pin)| {
+            let s = stats.get(sensor).cloned().unwrap_or_default();
+            SensorSeries {
+                sensor,
+                pin,
+                alias: state.config.sensor_alias(sensor).unwrap_or_default(),
+                reading: data
+                    .temperatures
+                    .iter()
+                    .find(|t| t.value > NO_TEMP && t.sensor == *sensor)
+                    .map(|t| (t.value, t.timestamp)),
+                reads: s.reads,
+                read_errors: s.read_errors,
+                crc_errors: s.crc_errors,
+                failed_measurements: s.failed_measurements,
+            }
+        })
+        .collect::<Vec<_>>();
+    write_sensor_metrics(&mut m, &series);
+
+    m.finish()
+}
+
+// EOF
@@ -1,10 +1,23 @@
 // mqtt.rs
 
-use esp_idf_svc::mqtt::{self, client::MessageId};
+use std::sync::atomic::AtomicBool;
+
+use esp_idf_svc::mqtt::{
+    self,
+    client::{EventPayload, MessageId},
+};
 use esp_idf_sys::EspError;
 
 use crate::*;
 
+pub const MQTT_STATUS_ONLINE: &str = "online";
+pub const MQTT_STATUS_OFFLINE: &str = "offline";
+
+/// Availability topic: retained `online` after connecting, `offline` as the Last Will
+pub fn mqtt_status_topic(config: &MyConfig) -> String {
+    format!("{}/status", config.mqtt_topic)
+}
+
 #[allow(unreachable_code)]
 pub async fn run_mqtt(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
     if state.ap_mode {
@@ -35,11 +48,19 @@ pub async fn run_mqtt(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
     sleep(Duration::from_secs(10)).await;
 
     info!("MQTT conn: {url} [{myid}]");
+    let status_topic = mqtt_status_topic(&state.config);
     let (client, conn) = match mqtt::client::EspAsyncMqttClient::new(
         &url,
         &mqtt::client::MqttClientConfiguration {
             client_id: Some(&myid),
             keep_alive_interval: Some(Duration::from_secs(25)),
+            // published by the broker when the keep-alive runs out
+            lwt: Some(mqtt::client::LwtConfiguration {
+                topic: &status_topic,
+                payload: MQTT_STATUS_OFFLINE.as_bytes(),
+                qos: mqtt::client::QoS::AtLeastOnce,
+                retain: true,
+            }),
             ..Default::default()
         },
     ) {
@@ -51,9 +72,13 @@ pub async fn run_mqtt(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
         }
     };
 
+    // set by the event loop on every (re)connect, subscriptions do not survive a reconnect,
+    // and availability and discovery are sent again as the LWT may have replaced `online`
+    let subscribe = Arc::new(AtomicBool::new(false));
+    let connected = Arc::new(AtomicBool::new(false));
     tokio::select! {
-        _ = Box::pin(data_sender(state.clone(), client)) => { error!("data_sender() ended."); }
-        _ = Box::pin(event_loop(state.clone(), conn)) => { error!("event_loop() ended."); }
+        _ = Box::pin(data_sender(state.clone(), client, subscribe.clone(), connected.clone())) => { error!("data_sender() ended."); }
+        _ = Box::pin(event_loop(state.clone(), conn, subscribe, connected)) => { error!("event_loop() ended."); }
     };
     Ok(())
 }
@@ -61,13 +86,84 @@ pub async fn run_mqtt(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
 async fn data_sender(
     state: Arc<Pin<Box<MyState>>>,
     mut client: mqtt::client::EspAsyncMqttClient,
+    subscribe: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
 ) -> anyhow::Result<()> {
     let mqtt_topic = state.config.mqtt_topic.clone();
+    let status_topic = mqtt_status_topic(&state.config);
+    let unit = state.config.unit;
+    // sensors with a published discovery config
+    let mut discovered = Vec::<String>::new();
 
     loop {
         sleep(Duration::from_secs(5)).await;
         let uptime = state.data.read().await.uptime;
 
+        if state.config.thermo_enable && subscribe.swap(false, Ordering::Relaxed) {
+            let topic = format!("{mqtt_topic}/thermostat/set");
+            info!("MQTT subscribing {topic}");
+            if let Err(e) = client
+                .subscribe(&topic, mqtt::client::QoS::AtLeastOnce)
+                .await
+            {
+                error!("MQTT subscribe error: {e}");
+                subscribe.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let reconnected = connected.swap(false, Ordering::Relaxed);
+        if reconnected {
+            Box::pin(mqtt_send(
+                &mut client,
+                &status_topic,
+                MQTT_STATUS_ONLINE,
+                true,
+            ))
+            .await?;
+        }
+
+        if state.config.mqtt_discovery {
+            // the sensor ids are placeholders until the first poll
+            let mut sensors = state
+                .data
+                .read()
+                .await
+                .temperatures
+                .iter()
+                .filter(|t| t.sensor.chars().all(|c| c.is_ascii_alphanumeric()))
+                .map(|t| t.sensor.clone())
+                .collect::<Vec<_>>();
+            sensors.sort();
+            sensors.dedup();
+            if reconnected || sensors != discovered {
+                Box::pin(publish_discovery(
+                    &state,
+                    &mut client,
+                    &discovered,
+                    &sensors,
+                ))
+                .await?;
+                discovered = sensors;
+            }
+        }
+
+        // alert state changes go out right away, not only with fresh data, and leave the
+        // queue only once they have been sent
+        loop {
+            let next = state.alert_queue.lock().await.pop_front();
+            let Some(alert) = next else {
+                break;
+            };
+            let topic = format!("{mqtt_topic}/alert/{}", alert.name);
+            let mqtt_data = serde_json::to_string(&alert)?;
+            if let Err(e) = Box::pin(mqtt_send(&mut client, &topic, &mqtt_data, false)).await {
+                // kept at the head of the queue and retried on the next round
+                error!("MQTT alert {} not sent: {e}", alert.name);
+                state.alert_queue.lock().await.push_front(alert);
+                break;
+            }
+        }
+
         {
             let mut fresh_data = state.fresh_data.write().await;
             if !*fresh_data {
@@ -79,22 +175,92 @@ async fn data_sender(
         {
             let mut topic = format!("{mqtt_topic}/uptime");
             let mut mqtt_data = format!("{{ \"uptime\": {} }}", uptime);
-            Box::pin(mqtt_send(&mut client, &topic, &mqtt_data)).await?;
+            Box::pin(mqtt_send(&mut client, &topic, &mqtt_data, false)).await?;
 
             let data = state.data.read().await;
             for v in data.temperatures.iter().filter(|v| v.value > NO_TEMP) {
+                let v = v.in_unit(unit);
                 topic = format!("{mqtt_topic}/{}", v.sensor);
-                mqtt_data = format!("{{ \"temperature\": {} }}", v.value);
-                Box::pin(mqtt_send(&mut client, &topic, &mqtt_data)).await?;
+                mqtt_data = format!(
+                    "{{ \"temperature\": {}, \"unit\": \"{unit}\", \"timestamp\": {}, \"retries\": {}, \"conversion_ms\": {}",
+                    v.value, v.timestamp, v.retries, v.conversion_ms
+                );
+                if let (Some(rate_min), Some(rate_h)) = (v.rate_min, v.rate_h) {
+                    mqtt_data += &format!(
+                        ", \"rate_min\": {rate_min}, \"rate_h\": {rate_h}, \"trend\": \"{}\"",
+                        v.trend.as_str()
+                    );
+                }
+                mqtt_data += " }";
+                Box::pin(mqtt_send(
+                    &mut client,
+                    &topic,
+                    &mqtt_data,
+                    state.config.mqtt_retain,
+                ))
+                .await?;
+            }
+            drop(data);
+
+            if !state.config.dd_sensor.is_empty() {
+                let dd = state.degree_days.read().await.in_unit(unit);
+                topic = format!("{mqtt_topic}/degreedays");
+                mqtt_data = format!(
+                    "{{ \"heat_today\": {}, \"cool_today\": {}, \"heat_yesterday\": {}, \"cool_yesterday\": {}, \"heat_month\": {}, \"cool_month\": {}, \"unit\": \"{unit}\" }}",
+                    dd.heat_today,
+                    dd.cool_today,
+                    dd.heat_yesterday,
+                    dd.cool_yesterday,
+                    dd.heat_month,
+                    dd.cool_month
+                );
+                Box::pin(mqtt_send(&mut client, &topic, &mqtt_data, false)).await?;
+            }
+
+            if state.config.thermo_enable {
+                let status = state.thermostat.read().await.status(&state.config);
+                topic = format!("{mqtt_topic}/thermostat");
+                mqtt_data = serde_json::to_string(&status)?;
+                Box::pin(mqtt_send(&mut client, &topic, &mqtt_data, false)).await?;
+            }
+
+            if state.config.pwm_enable {
+                let status = state.pwm.read().await.status(&state.config);
+                topic = format!("{mqtt_topic}/pwm");
+                mqtt_data = serde_json::to_string(&status)?;
+                Box::pin(mqtt_send(&mut client, &topic, &mqtt_data, false)).await?;
             }
         }
     }
 }
 
+// Home Assistant discovery for `sensors`, and removal of the ones no longer present
+async fn publish_discovery(
+    state: &MyState,
+    client: &mut mqtt::client::EspAsyncMqttClient,
+    previous: &[String],
+    sensors: &[String],
+) -> anyhow::Result<()> {
+    let config = &state.config;
+    let myid = state.myid.read().await.clone();
+    info!("MQTT discovery for {} sensor(s)", sensors.len());
+
+    for gone in previous.iter().filter(|s| !sensors.contains(s)) {
+        // an empty retained config deletes the entity
+        let topic = hass_config_topic(config, &myid, gone);
+        Box::pin(mqtt_send(client, &topic, "", true)).await?;
+    }
+    for (topic, payload) in hass_discovery(state, sensors).await? {
+        Box::pin(mqtt_send(client, &topic, &payload, true)).await?;
+    }
+    Ok(())
+}
+
 async fn mqtt_send(
     client: &mut mqtt::client::EspAsyncMqttClient,
     topic: &str,
     data: &str,
+    retain: bool,
 ) -> Result<MessageId, EspError> {
     info!("MQTT sending {topic} {data}");
 
@@ -102,7 +268,7 @@ async fn mqtt_send(
         .publish(
             topic,
             mqtt::client::QoS::AtLeastOnce,
-            false,
+            retain,
             data.as_bytes(),
         )
         .await;
@@ -114,11 +280,36 @@ async fn mqtt_send(
 }
 
 async fn event_loop(
-    _state: Arc<Pin<Box<MyState>>>,
+    state: Arc<Pin<Box<MyState>>>,
     mut conn: mqtt::client::EspAsyncMqttConnection,
+    subscribe: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
 ) -> anyhow::Result<()> {
+    let set_topic = format!("{}/thermostat/set", state.config.mqtt_topic);
     while let Ok(notification) = Box::pin(conn.next()).await {
         info!("MQTT received: {:?}", notification.payload());
+        match notification.payload() {
+            EventPayload::Connected(_) => {
+                subscribe.store(true, Ordering::Relaxed);
+                connected.store(true, Ordering::Relaxed);
+            }
+            EventPayload::Received {
+                topic: Some(topic),
+                data,
+                ..
+            } if topic == set_topic => {
+                let result =
+                    serde_json::from_slice::<ThermostatCommand>(data).map_err(anyhow::Error::from);
+                let result = match result {
+                    Ok(cmd) => Box::pin(set_thermostat(&state, cmd)).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!("MQTT thermostat command error: {e:#}");
+                }
+            }
+            _ => {}
+        }
     }
 
     error!("MQTT connection closed.");
@@ -0,0 +1,203 @@
+// onewire.rs
+//
+// Common interface for the 1-Wire bus backends. `measure.rs` only talks to
+// `dyn OneWireBus`, so a bus can be driven by the RMT peripheral (`rmt_ow.rs`),
+// by software bit-banging (`bitbang_ow.rs`) or through a DS2482 I2C bridge
+// (`ds2482.rs`).
+
+use esp_idf_sys::{ESP_ERR_INVALID_STATE, ESP_ERR_NOT_FOUND, EspError};
+
+use crate::*;
+
+pub trait OneWireBus {
+    /// Reset pulse. Fails with `ESP_ERR_NOT_FOUND` when no device answered with presence.
+    fn reset(&self) -> Result<(), EspError>;
+
+    fn read(&self, buf: &mut [u8]) -> Result<(), EspError>;
+
+    fn write(&self, data: &[u8]) -> Result<(), EspError>;
+
+    fn search_devices(&mut self) -> Result<Vec<OWAddress>, EspError>;
+
+    /// Arm a strong pull-up after the last byte of the next `write()`, or release it.
+    /// Backends without strong pull-up support treat this as a no-op, see
+    /// `OWBus::has_strong_pullup()`.
+    fn strong_pullup(&self, enable: bool) -> Result<(), EspError>;
+}
+
+/// ROM search for backends that provide the search "triplet" primitive:
+/// read id bit, read complement bit, write the chosen direction.
+pub(crate) trait TripletSearch {
+    fn search_reset(&mut self) -> Result<(), EspError>;
+
+    fn search_write_byte(&mut self, byte: u8) -> Result<(), EspError>;
+
+    /// Returns (id_bit, cmp_id_bit, taken direction)
+    fn search_triplet(&mut self, direction: bool) -> Result<(bool, bool, bool), EspError>;
+
+    fn search_rom(&mut self) -> Result<Vec<OWAddress>, EspError> {
+        let mut devices = Vec::new();
+        let mut rom = 0u64;
+        let mut last_discrepancy = 0u32;
+
+        loop {
+            match self.search_reset() {
+                Ok(_) => {}
+                Err(e) if e.code() == ESP_ERR_NOT_FOUND => break,
+                Err(e) => return Err(e),
+            }
+            self.search_write_byte(OWCommand::Search as u8)?;
+
+            let mut last_zero = 0u32;
+            for bit in 1..=64u32 {
+                let direction = if bit < last_discrepancy {
+                    rom & (1 << (bit - 1)) != 0
+                } else {
+                    bit == last_discrepancy
+                };
+
+                let (id_bit, cmp_id_bit, direction) = self.search_triplet(direction)?;
+                if id_bit && cmp_id_bit {
+                    // nobody answered, bus glitch or devices left mid-search
+                    return Ok(devices);
+                }
+                if !id_bit && !cmp_id_bit && !direction {
+                    last_zero = bit;
+                }
+
+                if direction {
+                    rom |= 1 << (bit - 1);
+                } else {
+                    rom &= !(1 << (bit - 1));
+                }
+            }
+
+            devices.push(OWAddress::new(rom));
+            last_discrepancy = last_zero;
+            if last_discrepancy == 0 {
+                break;
+            }
+        }
+
+        Ok(devices)
+    }
+}
+
+pub enum OWBus {
+    Gpio {
+        gpio: PinId,
+        // taken by the open handle and given back by `close()`, so only one driver
+        // can ever use the pin
+        pin: Option<AnyIOPin<'static>>,
+        // configured for bit-banging, RMT is not even tried
+        bitbang: bool,
+        // a fallback to bit-banging has been logged already
+        fallback_logged: bool,
+    },
+    Ds2482(Ds2482Channel),
+}
+
+/// An open bus, holding the GPIO of a `OWBus::Gpio` until it is passed to `OWBus::close()`
+pub enum OWHandle {
+    Rmt(OWDriver<'static>),
+    BitBang(BitBangOW<'static>),
+    Ds2482(Ds2482Channel),
+}
+
+impl OWHandle {
+    pub fn bus(&mut self) -> &mut dyn OneWireBus {
+        match self {
+            OWHandle::Rmt(w) => w,
+            OWHandle::BitBang(w) => w,
+            OWHandle::Ds2482(w) => w,
+        }
+    }
+
+    fn into_pin(self) -> Option<AnyIOPin<'static>> {
+        match self {
+            OWHandle::Rmt(w) => Some(w.into_pin()),
+            OWHandle::BitBang(w) => Some(w.into_pin()),
+            OWHandle::Ds2482(_) => None,
+        }
+    }
+}
+
+impl OWBus {
+    pub fn gpio(pin: AnyIOPin<'static>, bitbang: bool) -> Self {
+        OWBus::Gpio {
+            gpio: pin.pin(),
+            pin: Some(pin),
+            bitbang,
+            fallback_logged: false,
+        }
+    }
+
+    /// Open the bus for a batch of transactions. RMT channels are a scarce resource,
+    /// so the RMT backend only holds one while the returned handle is open.
+    /// If no RMT channel can be had, this session is bit-banged instead, and the next
+    /// open tries RMT again.
+    ///
+    /// There can be only one open handle per bus: it takes the pin, and opening the bus
+    /// again fails until the handle has been given back to `close()`. After the boot-time
+    /// scan, buses are owned by the 1-Wire worker thread (`ow_worker.rs`).
+    pub fn open(&mut self) -> Result<OWHandle, EspError> {
+        match self {
+            OWBus::Gpio {
+                gpio,
+                pin,
+                bitbang,
+                fallback_logged,
+            } => {
+                let Some(mut pin) = pin.take() else {
+                    return Err(EspError::from_infallible::<ESP_ERR_INVALID_STATE>());
+                };
+                if !*bitbang {
+                    match OWDriver::new(pin) {
+                        Ok(w) => return Ok(OWHandle::Rmt(w)),
+                        Err((e, p)) => {
+                            pin = p;
+                            if !*fallback_logged {
+                                warn!(
+                                    "RMT 1-Wire bus on gpio{gpio} failed: {e}, bit-banging until RMT is available"
+                                );
+                                *fallback_logged = true;
+                            } else {
+                                debug!("RMT 1-Wire bus on gpio{gpio} failed: {e}, bit-banging");
+                            }
+                        }
+                    }
+                }
+                Ok(OWHandle::BitBang(BitBangOW::new(pin)?))
+            }
+            OWBus::Ds2482(channel) => Ok(OWHandle::Ds2482(channel.clone())),
+        }
+    }
+
+    /// Close a handle returned by `open()` on this bus, taking back its pin
+    pub fn close(&mut self, handle: OWHandle) {
+        if let OWBus::Gpio { pin, .. } = self {
+            *pin = handle.into_pin();
+        }
+    }
+
+    /// True if opening the bus takes RMT channels
+    pub fn uses_rmt(&self) -> bool {
+        matches!(self, OWBus::Gpio { bitbang: false, .. })
+    }
+
+    /// True if the backend can hold a strong pull-up for parasite-powered probes
+    pub fn has_strong_pullup(&self) -> bool {
+        matches!(self, OWBus::Ds2482(_))
+    }
+
+    /// Buses in the same group share hardware and must not be driven concurrently
+    pub fn hw_group(&self) -> u32 {
+        match self {
+            OWBus::Gpio { gpio, .. } => u32::from(*gpio),
+            // all channels of the bridge share its strong pull-up and 1-Wire master
+            OWBus::Ds2482(_) => u32::MAX,
+        }
+    }
+}
+
+// EOF
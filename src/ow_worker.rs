@@ -0,0 +1,228 @@
+// ow_worker.rs
+//
+// All 1-Wire backends are blocking (FFI calls, I2C transfers, bit-banged slots),
+// so the buses are owned by a dedicated thread and the async side talks to it
+// over a request/response channel. This keeps the Tokio runtime (HTTP, MQTT,
+// ESPHome) responsive while a bus transaction or timeout is in flight. The thread
+// runs one operation at a time, so buses measured "concurrently" only overlap in
+// their conversion waits and pauses, never in bus traffic.
+
+use esp_idf_hal::task::thread::ThreadSpawnConfiguration;
+use esp_idf_sys::{ESP_ERR_INVALID_STATE, EspError};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::*;
+
+const OW_WORKER_STACK_SIZE: usize = 8192;
+const OW_WORKER_PRIORITY: u8 = 5;
+
+enum OWOp {
+    Open,
+    Close,
+    Reset,
+    Read(usize),
+    Write(Vec<u8>),
+    StrongPullup(bool),
+    Search,
+}
+
+enum OWReply {
+    Done,
+    Data(Vec<u8>),
+    Devices(Vec<OWAddress>),
+}
+
+struct OWRequest {
+    bus: usize,
+    op: OWOp,
+    resp: Option<oneshot::Sender<Result<OWReply, EspError>>>,
+}
+
+/// Move the buses to the 1-Wire worker thread, returning an async client for each of them
+/// in the same order.
+pub fn start_onewire_worker(buses: Vec<OWBus>) -> anyhow::Result<Vec<OWBusClient>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let clients = buses
+        .iter()
+        .enumerate()
+        .map(|(bus, b)| OWBusClient {
+            bus,
+            hw_group: b.hw_group(),
+            uses_rmt: b.uses_rmt(),
+            strong_pullup: b.has_strong_pullup(),
+            tx: tx.clone(),
+        })
+        .collect();
+
+    ThreadSpawnConfiguration {
+        name: Some(c"onewire"),
+        stack_size: OW_WORKER_STACK_SIZE,
+        priority: OW_WORKER_PRIORITY,
+        // keep bus timing away from the WiFi stack on the dual-core ESP32
+        #[cfg(feature = "esp-wroom-32")]
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }
+    .set()?;
+    let spawned = std::thread::Builder::new()
+        .stack_size(OW_WORKER_STACK_SIZE)
+        .spawn(move || onewire_worker(buses, rx));
+    ThreadSpawnConfiguration::default().set()?;
+    spawned?;
+
+    Ok(clients)
+}
+
+fn onewire_worker(mut buses: Vec<OWBus>, mut rx: mpsc::UnboundedReceiver<OWRequest>) {
+    info!("1-Wire worker started with {} bus(es)", buses.len());
+    let mut handles = buses.iter().map(|_| None).collect::<Vec<_>>();
+
+    while let Some(req) = rx.blocking_recv() {
+        let result = match req.op {
+            OWOp::Open => {
+                // close a stale handle first, there can be only one per bus
+                if let Some(h) = handles[req.bus].take() {
+                    buses[req.bus].close(h);
+                }
+                buses[req.bus].open().map(|h| {
+                    handles[req.bus] = Some(h);
+                    OWReply::Done
+                })
+            }
+            OWOp::Close => {
+                if let Some(h) = handles[req.bus].take() {
+                    buses[req.bus].close(h);
+                }
+                Ok(OWReply::Done)
+            }
+            op => match handles[req.bus].as_mut() {
+                None => Err(EspError::from_infallible::<ESP_ERR_INVALID_STATE>()),
+                Some(h) => run_op(h.bus(), op),
+            },
+        };
+
+        if let Some(resp) = req.resp {
+            // the requester may have given up waiting
+            let _ = resp.send(result);
+        }
+    }
+
+    error!("1-Wire worker channel closed.");
+}
+
+fn run_op(bus: &mut dyn OneWireBus, op: OWOp) -> Result<OWReply, EspError> {
+    match op {
+        OWOp::Reset => bus.reset().map(|_| OWReply::Done),
+        OWOp::Read(len) => {
+            let mut buf = vec![0u8; len];
+            bus.read(&mut buf)?;
+            Ok(OWReply::Data(buf))
+        }
+        OWOp::Write(data) => bus.write(&data).map(|_| OWReply::Done),
+        OWOp::StrongPullup(enable) => bus.strong_pullup(enable).map(|_| OWReply::Done),
+        OWOp::Search => bus.search_devices().map(OWReply::Devices),
+        OWOp::Open | OWOp::Close => unreachable!(),
+    }
+}
+
+/// Async proxy for one bus owned by the worker thread.
+pub struct OWBusClient {
+    bus: usize,
+    hw_group: u32,
+    uses_rmt: bool,
+    strong_pullup: bool,
+    tx: mpsc::UnboundedSender<OWRequest>,
+}
+
+impl OWBusClient {
+    /// Buses in the same group share hardware and must not be driven concurrently
+    pub fn hw_group(&self) -> u32 {
+        self.hw_group
+    }
+
+    /// True if opening the bus takes RMT channels
+    pub fn uses_rmt(&self) -> bool {
+        self.uses_rmt
+    }
+
+    /// True if the backend can hold a strong pull-up for parasite-powered probes
+    pub fn has_strong_pullup(&self) -> bool {
+        self.strong_pullup
+    }
+
+    /// Open the bus for a batch of operations, it is closed again when the session is dropped.
+    pub async fn open(&self) -> Result<OWSession<'_>, EspError> {
+        self.request(OWOp::Open).await?;
+        Ok(OWSession { client: self })
+    }
+
+    async fn request(&self, op: OWOp) -> Result<OWReply, EspError> {
+        let (resp, rx) = oneshot::channel();
+        self.tx
+            .send(OWRequest {
+                bus: self.bus,
+                op,
+                resp: Some(resp),
+            })
+            .map_err(|_| EspError::from_infallible::<ESP_ERR_INVALID_STATE>())?;
+        rx.await
+            .map_err(|_| EspError::from_infallible::<ESP_ERR_INVALID_STATE>())?
+    }
+}
+
+pub struct OWSession<'a> {
+    client: &'a OWBusClient,
+}
+
+impl OWSession<'_> {
+    pub fn has_strong_pullup(&self) -> bool {
+        self.client.has_strong_pullup()
+    }
+
+    pub async fn reset(&self) -> Result<(), EspError> {
+        self.client.request(OWOp::Reset).await.map(|_| ())
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> Result<(), EspError> {
+        match self.client.request(OWOp::Read(buf.len())).await? {
+            OWReply::Data(data) => {
+                buf.copy_from_slice(&data);
+                Ok(())
+            }
+            _ => Err(EspError::from_infallible::<ESP_ERR_INVALID_STATE>()),
+        }
+    }
+
+    pub async fn write(&self, data: &[u8]) -> Result<(), EspError> {
+        self.client
+            .request(OWOp::Write(data.to_vec()))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn strong_pullup(&self, enable: bool) -> Result<(), EspError> {
+        self.client
+            .request(OWOp::StrongPullup(enable))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn search(&self) -> Result<Vec<OWAddress>, EspError> {
+        match self.client.request(OWOp::Search).await? {
+            OWReply::Devices(devices) => Ok(devices),
+            _ => Err(EspError::from_infallible::<ESP_ERR_INVALID_STATE>()),
+        }
+    }
+}
+
+impl Drop for OWSession<'_> {
+    fn drop(&mut self) {
+        let _ = self.client.tx.send(OWRequest {
+            bus: self.client.bus,
+            op: OWOp::Close,
+            resp: None,
+        });
+    }
+}
+
+// EOF
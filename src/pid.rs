@@ -0,0 +1,232 @@
+// pid.rs
+//
+// PID controller math for the PWM output. This module deliberately has no
+// ESP-IDF or crate dependencies, its tests run on the host (`host-test/`).
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PidController {
+    gains: PidGains,
+    out_min: f32,
+    out_max: f32,
+    // false: output rises when below the setpoint (heating), true: above it (cooling)
+    reverse: bool,
+    integral: f32,
+    last_measurement: Option<f32>,
+}
+
+impl PidController {
+    pub fn new(gains: PidGains, out_min: f32, out_max: f32, reverse: bool) -> Self {
+        let (out_min, out_max) = if out_min <= out_max {
+            (out_min, out_max)
+        } else {
+            (out_max, out_min)
+        };
+        Self {
+            gains,
+            out_min,
+            out_max,
+            reverse,
+            integral: 0.0,
+            last_measurement: None,
+        }
+    }
+
+    /// Forget the integral and derivative history, e.g. after the sensor was unavailable
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_measurement = None;
+    }
+
+    /// One control step `dt` seconds after the previous one, returns the clamped output.
+    ///
+    /// The derivative acts on the measurement, so setpoint changes do not kick the output.
+    /// Anti-windup: the integral is frozen while the output is saturated in the direction
+    /// the error pushes it, and always kept within the output limits. It starts at zero
+    /// rather than at the lower limit, so a minimum duty does not bias the output.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        let sign = if self.reverse { -1.0 } else { 1.0 };
+        let error = sign * (setpoint - measurement);
+
+        let p = self.gains.kp * error;
+        let d = match self.last_measurement {
+            Some(last) if dt > 0.0 => -sign * self.gains.kd * (measurement - last) / dt,
+            _ => 0.0,
+        };
+        self.last_measurement = Some(measurement);
+
+        let integral = self.integral + self.gains.ki * error * dt.max(0.0);
+        let unclamped = p + integral + d;
+        let winding_up =
+            (unclamped > self.out_max && error > 0.0) || (unclamped < self.out_min && error < 0.0);
+        if !winding_up {
+            self.integral = integral.clamp(self.out_min.min(0.0), self.out_max);
+        }
+
+        (p + self.integral + d).clamp(self.out_min, self.out_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid(kp: f32, ki: f32, kd: f32) -> PidController {
+        PidController::new(PidGains { kp, ki, kd }, 0.0, 100.0, false)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn proportional() {
+        let mut c = pid(10.0, 0.0, 0.0);
+        assert_close(c.update(21.0, 20.0, 5.0), 10.0);
+        assert_close(c.update(21.0, 18.5, 5.0), 25.0);
+        // at or above the setpoint the output bottoms out
+        assert_close(c.update(21.0, 22.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn proportional_reverse() {
+        let mut c = PidController::new(
+            PidGains {
+                kp: 10.0,
+                ki: 0.0,
+                kd: 0.0,
+            },
+            0.0,
+            100.0,
+            true,
+        );
+        assert_close(c.update(21.0, 23.0, 5.0), 20.0);
+        assert_close(c.update(21.0, 20.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn integral_accumulates_over_dt() {
+        let mut c = pid(0.0, 0.5, 0.0);
+        assert_close(c.update(21.0, 20.0, 4.0), 2.0);
+        assert_close(c.update(21.0, 20.0, 4.0), 4.0);
+        // a negative error winds it back down
+        assert_close(c.update(21.0, 22.0, 2.0), 3.0);
+    }
+
+    #[test]
+    fn derivative_on_measurement() {
+        let mut c = pid(0.0, 0.0, 10.0);
+        // no previous measurement, no derivative
+        assert_close(c.update(21.0, 20.0, 2.0), 0.0);
+        // falling 1 degree in 2 s pushes the output up
+        assert_close(c.update(21.0, 19.0, 2.0), 5.0);
+        // a setpoint change alone does not kick the output
+        assert_close(c.update(30.0, 19.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn output_is_clamped() {
+        let mut c = PidController::new(
+            PidGains {
+                kp: 10.0,
+                ki: 0.0,
+                kd: 0.0,
+            },
+            20.0,
+            80.0,
+            false,
+        );
+        assert_close(c.update(21.0, 0.0, 5.0), 80.0);
+        assert_close(c.update(21.0, 40.0, 5.0), 20.0);
+    }
+
+    #[test]
+    fn swapped_limits() {
+        let mut c = PidController::new(
+            PidGains {
+                kp: 10.0,
+                ki: 0.0,
+                kd: 0.0,
+            },
+            80.0,
+            20.0,
+            false,
+        );
+        assert_close(c.update(21.0, 0.0, 5.0), 80.0);
+        assert_close(c.update(21.0, 40.0, 5.0), 20.0);
+    }
+
+    #[test]
+    fn anti_windup() {
+        let mut c = pid(10.0, 1.0, 0.0);
+        // a long time far below the setpoint saturates the output...
+        for _ in 0..100 {
+            assert_close(c.update(100.0, 0.0, 60.0), 100.0);
+        }
+        // ...but the integral did not wind up, so crossing the setpoint turns it off at once
+        assert_close(c.update(100.0, 101.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn integral_stays_within_limits() {
+        let mut c = pid(0.0, 1.0, 0.0);
+        assert_close(c.update(21.0, 20.0, 50.0), 50.0);
+        assert_close(c.update(21.0, 20.0, 50.0), 100.0);
+        assert_close(c.update(21.0, 20.0, 50.0), 100.0);
+        // it comes off the limit right away
+        assert_close(c.update(21.0, 22.0, 1.0), 99.0);
+    }
+
+    #[test]
+    fn reset_forgets_history() {
+        let mut c = pid(0.0, 1.0, 10.0);
+        c.update(21.0, 20.0, 10.0);
+        assert_close(c.update(21.0, 20.0, 10.0), 20.0);
+        c.reset();
+        // neither the integral nor a derivative from the old measurement
+        assert_close(c.update(21.0, 10.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn integral_starts_at_zero_with_minimum_output() {
+        let mut c = PidController::new(
+            PidGains {
+                kp: 0.0,
+                ki: 1.0,
+                kd: 0.0,
+            },
+            20.0,
+            80.0,
+            false,
+        );
+        // below the minimum duty the integral builds up from zero, not from 20
+        assert_close(c.update(21.0, 20.0, 10.0), 20.0);
+        assert_close(c.update(21.0, 20.0, 20.0), 30.0);
+        // and starts from zero again after a reset
+        c.reset();
+        assert_close(c.update(21.0, 20.0, 25.0), 25.0);
+        // a negative error does not wind it below zero while the output sits at the minimum
+        c.reset();
+        assert_close(c.update(21.0, 22.0, 50.0), 20.0);
+        assert_close(c.update(21.0, 20.0, 25.0), 25.0);
+    }
+
+    #[test]
+    fn zero_dt() {
+        let mut c = pid(10.0, 1.0, 10.0);
+        assert_close(c.update(21.0, 20.0, 0.0), 10.0);
+        // no derivative spike and no integral from a zero time step
+        assert_close(c.update(21.0, 18.0, 0.0), 30.0);
+    }
+}
+
+// EOF
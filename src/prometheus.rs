@@ -0,0 +1,203 @@
+// prometheus.rs
+//
+// Prometheus text exposition format for `GET /metrics` (`metrics.rs`). Prometheus
+// rejects the whole scrape when a metric repeats a label set, so the per-sensor
+// series are written only for real ROM ids, once per sensor and bus.
+
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Write},
+};
+
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            out: String::with_capacity(capacity),
+        }
+    }
+
+    pub fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP esp32temp_{name} {help}");
+        let _ = writeln!(self.out, "# TYPE esp32temp_{name} {kind}");
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.out, "esp32temp_{name}");
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{k}=\"{}\"", escape_label(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = write!(self.out, "{{{labels}}}");
+        }
+        let _ = writeln!(self.out, " {value}");
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// One sensor's labels, last reading and counters
+#[derive(Clone, Debug, Default)]
+pub struct SensorSeries<'a> {
+    pub sensor: &'a str,
+    pub pin: &'a str,
+    pub alias: &'a str,
+    // temperature in Celsius and its unix time
+    pub reading: Option<(f32, i64)>,
+    pub reads: u32,
+    pub read_errors: u32,
+    pub crc_errors: u32,
+    pub failed_measurements: u32,
+}
+
+type Counter = fn(&SensorSeries) -> u32;
+
+// 64-bit ROM id as formatted by `format_device_id`
+fn is_rom_id(sensor: &str) -> bool {
+    sensor.len() == 16 && sensor.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Temperature and counter metrics of `sensors`, skipping entries without a ROM id
+/// and repeated sensor/bus pairs
+pub fn write_sensor_metrics(m: &mut MetricsWriter, sensors: &[SensorSeries]) {
+    let mut seen = BTreeSet::new();
+    let sensors = sensors
+        .iter()
+        .filter(|s| is_rom_id(s.sensor) && seen.insert((s.sensor, s.pin)))
+        .collect::<Vec<_>>();
+    fn labels<'a>(s: &SensorSeries<'a>) -> [(&'static str, &'a str); 3] {
+        [("sensor", s.sensor), ("pin", s.pin), ("alias", s.alias)]
+    }
+
+    m.header("temperature_celsius", "gauge", "Last temperature reading.");
+    for s in sensors.iter() {
+        if let Some((value, _)) = s.reading {
+            m.sample("temperature_celsius", &labels(s), value);
+        }
+    }
+    m.header(
+        "temperature_timestamp_seconds",
+        "gauge",
+        "Unix time of the last temperature reading.",
+    );
+    for s in sensors.iter() {
+        if let Some((_, timestamp)) = s.reading {
+            m.sample("temperature_timestamp_seconds", &labels(s), timestamp);
+        }
+    }
+
+    let counters: [(&str, &str, Counter); 4] = [
+        ("sensor_reads_total", "Successful measurements.", |s| {
+            s.reads
+        }),
+        (
+            "sensor_read_errors_total",
+            "Scratchpad reads that failed.",
+            |s| s.read_errors,
+        ),
+        (
+            "sensor_crc_errors_total",
+            "Scratchpad reads with a CRC mismatch.",
+            |s| s.crc_errors,
+        ),
+        (
+            "sensor_failed_measurements_total",
+            "Measurements that failed after all retries.",
+            |s| s.failed_measurements,
+        ),
+    ];
+    for (name, help, value) in counters {
+        m.header(name, "counter", help);
+        for s in sensors.iter() {
+            m.sample(name, &labels(s), value(s));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(sensors: &[SensorSeries]) -> String {
+        let mut m = MetricsWriter::default();
+        write_sensor_metrics(&mut m, sensors);
+        m.finish()
+    }
+
+    #[test]
+    fn no_duplicate_label_sets() {
+        // the poller's placeholders until the first reads, and a sensor listed twice
+        let placeholder = SensorSeries {
+            sensor: "N/A",
+            pin: "N/A",
+            ..Default::default()
+        };
+        let sensors = [
+            placeholder.clone(),
+            placeholder,
+            SensorSeries {
+                sensor: "28FF0102030405A6",
+                pin: "gpio4",
+                alias: "freezer",
+                reading: Some((-18.5, 1_700_000_000)),
+                reads: 3,
+                ..Default::default()
+            },
+            SensorSeries {
+                sensor: "28FF0A0B0C0D0E0F",
+                pin: "gpio4",
+                read_errors: 1,
+                ..Default::default()
+            },
+            SensorSeries {
+                sensor: "28FF0102030405A6",
+                pin: "gpio4",
+                alias: "freezer",
+                ..Default::default()
+            },
+        ];
+        let out = render(&sensors);
+
+        let mut series = BTreeSet::new();
+        for line in out.lines().filter(|l| !l.starts_with('#')) {
+            let (key, _) = line.rsplit_once(' ').unwrap();
+            assert!(series.insert(key.to_string()), "duplicate series {key}");
+        }
+        assert!(!out.contains("N/A"));
+        // two real sensors, one of them with a reading
+        assert_eq!(series.len(), 2 + 4 * 2);
+        assert!(out.contains(
+            "esp32temp_temperature_celsius{sensor=\"28FF0102030405A6\",pin=\"gpio4\",alias=\"freezer\"} -18.5\n"
+        ));
+        assert!(out.contains(
+            "esp32temp_sensor_read_errors_total{sensor=\"28FF0A0B0C0D0E0F\",pin=\"gpio4\",alias=\"\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn label_escaping() {
+        let mut m = MetricsWriter::default();
+        m.sample("info", &[("version", "a\"b\\c\nd")], 1);
+        assert_eq!(
+            m.finish(),
+            "esp32temp_info{version=\"a\\\"b\\\\c\\nd\"} 1\n"
+        );
+    }
+}
+
+// EOF
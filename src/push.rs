@@ -0,0 +1,153 @@
+// push.rs
+//
+// Generic HTTP push for cloud dashboards and collectors that take simple GET or
+// POST requests (Emoncms, ThingSpeak-like services). The URL and body are templates
+// with `{placeholder}`s (`push_template.rs`), sent once per poll cycle or once per
+// new reading.
+
+use crate::*;
+
+const PUSH_TICK_S: u64 = 5;
+
+// Extra headers from `push_headers`, e.g. "X-Api-Key: abc123; X-Source: esp32temp"
+fn push_headers(config: &MyConfig) -> Vec<(String, String)> {
+    config
+        .push_headers
+        .split(';')
+        .filter_map(|entry| {
+            let (k, v) = entry.split_once(':')?;
+            let (k, v) = (k.trim(), v.trim());
+            (!k.is_empty()).then(|| (k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+async fn send(state: &MyState, headers: &[(String, String)], vars: &PushVars<'_>) {
+    let config = &state.config;
+    let url = vars.render(config.push_url.trim(), PushEncoding::Url);
+    let result = if config.push_method.eq_ignore_ascii_case("GET") {
+        state.http.get(&url, headers.to_vec()).await
+    } else {
+        // values end up inside JSON strings, so quotes or backslashes in an alias must not
+        // break the document
+        let encoding = if config
+            .push_content_type
+            .to_ascii_lowercase()
+            .contains("json")
+        {
+            PushEncoding::Json
+        } else {
+            PushEncoding::Plain
+        };
+        let body = vars.render(&config.push_body, encoding);
+        state
+            .http
+            .post(
+                &url,
+                &config.push_content_type,
+                headers.to_vec(),
+                body.into_bytes(),
+            )
+            .await
+    };
+
+    let expected = |status: u16| match config.push_expect {
+        0 => (200..300).contains(&status),
+        expect => status == expect,
+    };
+    match result {
+        Ok(status) if expected(status) => info!("HTTP push: HTTP {status}"),
+        Ok(status) => error!("HTTP push failed with unexpected HTTP {status}"),
+        Err(e) => error!("HTTP push error: {e:#}"),
+    }
+}
+
+pub async fn run_http_push(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if state.ap_mode || !state.config.push_enable || state.config.push_url.trim().is_empty() {
+        info!("HTTP push is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let config = &state.config;
+    let headers = push_headers(config);
+    let unit = config.unit;
+    info!(
+        "HTTP push {} {} {}",
+        config.push_method,
+        config.push_url,
+        if config.push_per_sensor {
+            "per reading"
+        } else {
+            "per poll cycle"
+        }
+    );
+
+    let mut readings = NewReadings::default();
+    loop {
+        sleep(Duration::from_secs(PUSH_TICK_S)).await;
+
+        let Some((cycle_ts, fresh)) = readings.take(&state).await else {
+            continue;
+        };
+        if fresh.is_empty() {
+            continue;
+        }
+        let device = state.myid.read().await.clone();
+
+        // keyed by alias and id, so both work in `{value:...}`, while `{json}` has
+        // one key per sensor, its alias if it has one
+        let mut readings_by_key = Vec::new();
+        let mut json = serde_json::Map::new();
+        for t in state.data.read().await.temperatures.iter() {
+            if t.value <= NO_TEMP {
+                continue;
+            }
+            let value = unit.from_celsius(t.value);
+            let alias = config.sensor_alias(&t.sensor);
+            if let Some(alias) = alias {
+                readings_by_key.push((alias.to_string(), value));
+            }
+            readings_by_key.push((t.sensor.clone(), value));
+            json.insert(
+                alias.unwrap_or(&t.sensor).to_string(),
+                serde_json::json!(value),
+            );
+        }
+        let json = serde_json::Value::Object(json).to_string();
+
+        if config.push_per_sensor {
+            for t in fresh.iter() {
+                let alias = config.sensor_alias(&t.sensor).unwrap_or(&t.sensor);
+                let vars = PushVars {
+                    vars: vec![
+                        ("device", device.clone()),
+                        ("sensor", t.sensor.clone()),
+                        ("alias", alias.to_string()),
+                        ("pin", t.iopin.clone()),
+                        ("value", unit.from_celsius(t.value).to_string()),
+                        ("unit", unit.to_string()),
+                        ("timestamp", t.timestamp.to_string()),
+                    ],
+                    readings: &readings_by_key,
+                    json: json.clone(),
+                };
+                Box::pin(send(&state, &headers, &vars)).await;
+            }
+        } else {
+            let vars = PushVars {
+                vars: vec![
+                    ("device", device),
+                    ("unit", unit.to_string()),
+                    ("timestamp", cycle_ts.to_string()),
+                ],
+                readings: &readings_by_key,
+                json,
+            };
+            Box::pin(send(&state, &headers, &vars)).await;
+        }
+    }
+}
+
+// EOF
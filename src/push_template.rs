@@ -0,0 +1,191 @@
+// push_template.rs
+//
+// `{placeholder}` templates of the HTTP push URL and body (`push.rs`), and the
+// percent-encoding and JSON escaping of the values inserted into them.
+
+/// Percent-encode a query string value
+pub fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Escape a value for use inside a JSON string, without the quotes
+pub fn json_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if c.is_control() => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// How the values are inserted into a template
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushEncoding {
+    Url,
+    // body with a JSON content type, the template puts string values in quotes
+    Json,
+    Plain,
+}
+
+/// Values for `{name}` placeholders, `{value:<id or alias>}` looks up any current reading
+/// and `{json}` is the already encoded JSON object of all readings
+pub struct PushVars<'a> {
+    pub vars: Vec<(&'static str, String)>,
+    pub readings: &'a [(String, f32)],
+    pub json: String,
+}
+
+impl PushVars<'_> {
+    fn lookup(&self, name: &str) -> Option<String> {
+        if let Some((_, value)) = self.vars.iter().find(|(k, _)| *k == name) {
+            return Some(value.clone());
+        }
+        let key = name.strip_prefix("value:")?;
+        self.readings
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.to_string())
+    }
+
+    /// Replace the placeholders in `template`, URL-encoding or JSON-escaping the values as
+    /// `encoding` says. Anything else in braces is left as it is.
+    pub fn render(&self, template: &str, encoding: PushEncoding) -> String {
+        let mut out = String::with_capacity(template.len() + 32);
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out += &rest[..start];
+            let tail = &rest[start..];
+            let Some(end) = tail.find('}') else {
+                // no closing brace anywhere after this one
+                out += tail;
+                rest = "";
+                break;
+            };
+            let name = &tail[1..end];
+            match self.lookup(name) {
+                Some(value) => match encoding {
+                    PushEncoding::Url => out += &url_encode(&value),
+                    PushEncoding::Json => out += &json_escape(&value),
+                    PushEncoding::Plain => out += &value,
+                },
+                None if name == "json" => match encoding {
+                    PushEncoding::Url => out += &url_encode(&self.json),
+                    PushEncoding::Json | PushEncoding::Plain => out += &self.json,
+                },
+                None => {
+                    // not a placeholder, e.g. a literal JSON brace
+                    out.push('{');
+                    rest = &tail[1..];
+                    continue;
+                }
+            }
+            rest = &tail[end + 1..];
+        }
+        out += rest;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(readings: &[(String, f32)]) -> PushVars<'_> {
+        PushVars {
+            vars: vec![
+                ("device", "esp32temp-a1b2c3".to_string()),
+                ("alias", "living room".to_string()),
+                ("value", "21.5".to_string()),
+            ],
+            readings,
+            json: r#"{"living room":21.5}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn plain_placeholders() {
+        let readings = [("freezer".to_string(), -18.5)];
+        let v = vars(&readings);
+        assert_eq!(
+            v.render("{device}: {value} / {value:FREEZER}", PushEncoding::Plain),
+            "esp32temp-a1b2c3: 21.5 / -18.5"
+        );
+        assert_eq!(
+            v.render("no placeholders", PushEncoding::Plain),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn url_encoded_values() {
+        let v = vars(&[]);
+        assert_eq!(
+            v.render("http://host/in?name={alias}&v={value}", PushEncoding::Url),
+            "http://host/in?name=living%20room&v=21.5"
+        );
+        // the template itself is not encoded
+        assert_eq!(v.render("{alias}", PushEncoding::Plain), "living room");
+        assert_eq!(url_encode("a&b=c/ä"), "a%26b%3Dc%2F%C3%A4");
+    }
+
+    #[test]
+    fn literal_json_braces() {
+        let v = vars(&[]);
+        assert_eq!(
+            v.render(
+                r#"{"device": "{device}", "t": {value}}"#,
+                PushEncoding::Plain
+            ),
+            r#"{"device": "esp32temp-a1b2c3", "t": 21.5}"#
+        );
+        assert_eq!(v.render("{unknown}", PushEncoding::Plain), "{unknown}");
+    }
+
+    #[test]
+    fn json_escaped_values() {
+        let mut v = vars(&[]);
+        v.vars
+            .push(("sensor", "attic \"north\" \\ 2\n".to_string()));
+        assert_eq!(
+            v.render(
+                r#"{"s": "{sensor}", "t": {value}, "all": {json}}"#,
+                PushEncoding::Json
+            ),
+            r#"{"s": "attic \"north\" \\ 2\n", "t": 21.5, "all": {"living room":21.5}}"#
+        );
+        assert_eq!(json_escape("a\u{1}b"), "a\\u0001b");
+        // `{json}` is URL-encoded like any value in a URL
+        assert_eq!(
+            v.render("?j={json}", PushEncoding::Url),
+            "?j=%7B%22living%20room%22%3A21.5%7D"
+        );
+    }
+
+    #[test]
+    fn unmatched_brace() {
+        let v = vars(&[]);
+        assert_eq!(v.render("{value} {", PushEncoding::Plain), "21.5 {");
+        assert_eq!(v.render("pre {value", PushEncoding::Plain), "pre {value");
+        assert_eq!(
+            v.render(r#"{"t": {value}, "x": {"#, PushEncoding::Plain),
+            r#"{"t": 21.5, "x": {"#
+        );
+    }
+}
+
+// EOF
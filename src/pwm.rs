@@ -0,0 +1,189 @@
+// pwm.rs
+//
+// PWM output for fans and valve actuators (0-10 V through an RC filter),
+// driven by the PID controller in `pid.rs` from one configured sensor.
+// The controller steps once per new reading, with the time since the previous
+// one, and the duty is held in between. Without a fresh reading the output goes
+// to the configured failsafe duty.
+
+use esp_idf_hal::ledc::LedcDriver;
+
+use crate::*;
+
+const PWM_TICK_S: u64 = 5;
+// a reading older than this many poll intervals counts as unavailable
+const PWM_STALE_POLLS: i64 = 3;
+
+pub struct PwmControl {
+    driver: Option<LedcDriver<'static>>,
+    pid: PidController,
+    pub setpoint: f32,
+    pub duty: f32,
+    pub failsafe: bool,
+    pub temperature: Option<f32>,
+    // unix time of the reading the controller last stepped on
+    last_reading: Option<i64>,
+}
+
+/// PWM control status as reported over HTTP, in the display unit; duty in percent
+#[derive(Clone, Debug, Serialize)]
+pub struct PwmStatus {
+    pub enabled: bool,
+    pub sensor: String,
+    pub setpoint: f32,
+    pub unit: TempUnit,
+    pub temperature: Option<f32>,
+    pub duty: f32,
+    pub failsafe: bool,
+}
+
+/// Runtime change of the setpoint (in the display unit)
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PwmCommand {
+    pub setpoint: Option<f32>,
+}
+
+impl PwmControl {
+    pub fn new(config: &MyConfig, driver: Option<LedcDriver<'static>>) -> Self {
+        let pid = PidController::new(
+            PidGains {
+                kp: config.pwm_kp,
+                ki: config.pwm_ki,
+                kd: config.pwm_kd,
+            },
+            config.pwm_min,
+            config.pwm_max,
+            config.pwm_reverse,
+        );
+        let mut pwm = Self {
+            driver,
+            pid,
+            setpoint: config.pwm_setpoint,
+            duty: 0.0,
+            failsafe: false,
+            temperature: None,
+            last_reading: None,
+        };
+        if let Err(e) = pwm.drive(config.pwm_failsafe) {
+            error!("PWM output init error: {e:#}");
+        }
+        pwm
+    }
+
+    pub fn status(&self, config: &MyConfig) -> PwmStatus {
+        let unit = config.unit;
+        PwmStatus {
+            enabled: config.pwm_enable,
+            sensor: config.pwm_sensor.clone(),
+            setpoint: unit.from_celsius(self.setpoint),
+            unit,
+            temperature: self.temperature.map(|t| unit.from_celsius(t)),
+            duty: self.duty,
+            failsafe: self.failsafe,
+        }
+    }
+
+    fn drive(&mut self, duty: f32) -> anyhow::Result<()> {
+        let duty = duty.clamp(0.0, 100.0);
+        if let Some(driver) = self.driver.as_mut() {
+            let max = driver.get_max_duty();
+            driver.set_duty((max as f32 * duty / 100.0).round() as u32)?;
+        }
+        self.duty = duty;
+        Ok(())
+    }
+
+    // `reading` is the latest (temperature, unix time), None when there is no fresh one
+    fn step(&mut self, config: &MyConfig, reading: Option<(f32, i64)>) -> anyhow::Result<()> {
+        let duty = match reading {
+            // nothing new since the last step, keep the duty
+            Some((_, ts)) if self.last_reading == Some(ts) => return Ok(()),
+            Some((t, ts)) => {
+                let dt = self
+                    .last_reading
+                    .map(|last| (ts - last).max(0) as f32)
+                    .unwrap_or(0.0);
+                self.last_reading = Some(ts);
+                self.temperature = Some(t);
+                self.failsafe = false;
+                self.pid.update(self.setpoint, t, dt)
+            }
+            None => {
+                // start over once readings come back
+                self.pid.reset();
+                self.last_reading = None;
+                self.temperature = None;
+                self.failsafe = true;
+                config.pwm_failsafe
+            }
+        };
+        self.drive(duty)
+    }
+
+    // A new setpoint applies right away, without waiting for the next reading
+    fn set_setpoint(&mut self, setpoint: f32) -> anyhow::Result<()> {
+        self.setpoint = setpoint;
+        match self.temperature {
+            Some(t) if !self.failsafe => {
+                let duty = self.pid.update(setpoint, t, 0.0);
+                self.drive(duty)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub async fn run_pwm_control(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if !state.config.pwm_enable {
+        info!("PWM control is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let stale_s = PWM_STALE_POLLS * state.config.delay.max(PWM_TICK_S) as i64;
+    loop {
+        let now = Utc::now().timestamp();
+        let reading = {
+            let data = state.data.read().await;
+            data.temperatures
+                .iter()
+                .find(|t| {
+                    t.value > NO_TEMP
+                        && t.sensor.eq_ignore_ascii_case(&state.config.pwm_sensor)
+                        && now - t.timestamp <= stale_s
+                })
+                .map(|t| (t.value, t.timestamp))
+        };
+
+        if let Err(e) = state.pwm.write().await.step(&state.config, reading) {
+            error!("PWM output error: {e:#}");
+        }
+
+        sleep(Duration::from_secs(PWM_TICK_S)).await;
+    }
+}
+
+/// Apply a setpoint change from HTTP or ESPHome and persist it.
+pub async fn set_pwm_control(state: &MyState, cmd: PwmCommand) -> anyhow::Result<PwmStatus> {
+    let (status, setpoint) = {
+        let mut pwm = state.pwm.write().await;
+        if let Some(s) = cmd.setpoint {
+            let s = state.config.unit.to_celsius(s);
+            if !(THERMO_SETPOINT_MIN..=THERMO_SETPOINT_MAX).contains(&s) {
+                bail!("Setpoint out of range");
+            }
+            pwm.set_setpoint(s)?;
+            info!("PWM control setpoint set to {s}");
+        }
+        (pwm.status(&state.config), pwm.setpoint)
+    };
+
+    state
+        .save_runtime_config(|config| config.pwm_setpoint = setpoint)
+        .await?;
+    *state.fresh_data.write().await = true;
+    Ok(status)
+}
+
+// EOF
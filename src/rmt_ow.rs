@@ -5,7 +5,7 @@
 // so we can enable `onewire_bus_config_t.flags.en_pull_up`, which the HAL
 // wrapper does not currently expose.
 
-use core::{marker::PhantomData, ptr};
+use core::ptr;
 
 use esp_idf_sys::*;
 
@@ -15,6 +15,10 @@ use crate::*;
 pub struct OWAddress(u64);
 
 impl OWAddress {
+    pub const fn new(address: u64) -> Self {
+        Self(address)
+    }
+
     pub const fn address(&self) -> u64 {
         self.0
     }
@@ -62,14 +66,15 @@ impl Drop for DeviceSearch<'_, '_> {
     }
 }
 
-#[derive(Debug)]
 pub struct OWDriver<'a> {
     handle: onewire_bus_handle_t,
-    _pin: PhantomData<&'a mut ()>,
+    // held until `into_pin()`, so no other driver can take the GPIO meanwhile
+    pin: Option<AnyIOPin<'a>>,
 }
 
 impl<'a> OWDriver<'a> {
-    pub fn new(pin: impl gpio::Pin + 'a) -> Result<Self, EspError> {
+    /// Gives the pin back with the error, e.g. when no RMT channel is free
+    pub fn new(pin: AnyIOPin<'a>) -> Result<Self, (EspError, AnyIOPin<'a>)> {
         let mut flags = onewire_bus_config_t_onewire_bus_config_flags::default();
         flags.set_en_pull_up(1);
 
@@ -80,14 +85,22 @@ impl<'a> OWDriver<'a> {
         let rmt_config = onewire_bus_rmt_config_t { max_rx_bytes: 10 };
 
         let mut handle: onewire_bus_handle_t = ptr::null_mut();
-        esp!(unsafe { onewire_new_bus_rmt(&bus_config, &rmt_config, &mut handle) })?;
+        if let Err(e) = esp!(unsafe { onewire_new_bus_rmt(&bus_config, &rmt_config, &mut handle) })
+        {
+            return Err((e, pin));
+        }
 
         Ok(Self {
             handle,
-            _pin: PhantomData,
+            pin: Some(pin),
         })
     }
 
+    /// Delete the bus, releasing its RMT channels, and give back the pin
+    pub fn into_pin(mut self) -> AnyIOPin<'a> {
+        self.pin.take().unwrap()
+    }
+
     pub const fn handle(&self) -> onewire_bus_handle_t {
         self.handle
     }
@@ -119,6 +132,30 @@ impl Drop for OWDriver<'_> {
 
 unsafe impl Send for OWDriver<'_> {}
 
+impl OneWireBus for OWDriver<'_> {
+    fn reset(&self) -> Result<(), EspError> {
+        OWDriver::reset(self)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<(), EspError> {
+        OWDriver::read(self, buf)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), EspError> {
+        OWDriver::write(self, data)
+    }
+
+    fn search_devices(&mut self) -> Result<Vec<OWAddress>, EspError> {
+        self.search()?.collect()
+    }
+
+    // The RMT backend only has the (weak) pull-up enabled in `new()`, so this is a no-op
+    // and parasite-powered probes are warned about when their power mode is read.
+    fn strong_pullup(&self, _enable: bool) -> Result<(), EspError> {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum OWCommand {
@@ -1,20 +1,25 @@
 // state.rs
 
+use std::collections::{BTreeMap, VecDeque};
+
+use tokio::sync::mpsc;
+
 use crate::*;
 
 pub const AP_MODE_NVS_KEY: &str = "boot_ap";
 
 pub struct MyOnewire {
-    pub pin: AnyIOPin<'static>,
+    pub bus: Mutex<OWBusClient>,
     pub name: String,
     pub ids: Vec<OWAddress>,
 }
-unsafe impl Send for MyOnewire {}
-unsafe impl Sync for MyOnewire {}
 
 pub struct MyState {
     pub ap_mode: bool,
+    // as loaded at boot, what all tasks run with
     pub config: MyConfig,
+    // as saved in NVS for the next boot, with the changes made since
+    pub saved_config: RwLock<MyConfig>,
     pub ota_slot: String,
 
     pub api_cnt: AtomicU32,
@@ -28,12 +33,24 @@ pub struct MyState {
     pub sensors: RwLock<Vec<MyOnewire>>,
     pub data: RwLock<TempValues>,
     pub fresh_data: RwLock<bool>,
+    pub degree_days: RwLock<DegreeDays>,
+    pub sensor_stats: SensorStatsMap,
+    pub thermostat: RwLock<Thermostat>,
+    pub pwm: RwLock<PwmControl>,
+    pub alerts: RwLock<Vec<Alert>>,
+    pub alert_queue: AlertQueue,
+    pub http: HttpWorkerClient,
+    pub measure_tx: mpsc::UnboundedSender<MeasureRequest>,
+    // set once poll_sensors takes requests from measure_rx
+    pub poller_ready: RwLock<bool>,
+    pub measure_rx: Mutex<Option<mpsc::UnboundedReceiver<MeasureRequest>>>,
     pub nvs: RwLock<nvs::EspNvs<nvs::NvsDefault>>,
     pub led: RwLock<PinDriver<'static, Output>>,
     pub reset: RwLock<bool>,
 }
 
 impl MyState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         ap_mode: bool,
         config: MyConfig,
@@ -41,10 +58,20 @@ impl MyState {
         ota_slot: String,
         onewire_pins: Vec<MyOnewire>,
         temp_data: TempValues,
+        degree_days: DegreeDays,
+        thermostat: Thermostat,
+        pwm: PwmControl,
+        http: HttpWorkerClient,
         led: PinDriver<'static, Output>,
     ) -> Self {
+        let (measure_tx, measure_rx) = mpsc::unbounded_channel();
+        let alerts = AlertRule::parse_list(&config.alert_rules)
+            .into_iter()
+            .map(Alert::new)
+            .collect();
         MyState {
             ap_mode,
+            saved_config: RwLock::new(config.clone()),
             config,
             ota_slot,
             api_cnt: 0.into(),
@@ -58,6 +85,16 @@ impl MyState {
             sensors: RwLock::new(onewire_pins),
             data: RwLock::new(temp_data),
             fresh_data: RwLock::new(false),
+            degree_days: RwLock::new(degree_days),
+            sensor_stats: RwLock::new(BTreeMap::new()),
+            thermostat: RwLock::new(thermostat),
+            pwm: RwLock::new(pwm),
+            alerts: RwLock::new(alerts),
+            alert_queue: Mutex::new(VecDeque::new()),
+            http,
+            measure_tx,
+            poller_ready: RwLock::new(false),
+            measure_rx: Mutex::new(Some(measure_rx)),
             nvs: RwLock::new(nvs),
             led: RwLock::new(led),
             reset: RwLock::new(false),
@@ -82,6 +119,28 @@ impl MyState {
         self.set_led(false).await
     }
 
+    /// Save a setting changed at runtime (thermostat mode/setpoint, PWM setpoint, log levels)
+    /// for the next boot, on top of the config saved last
+    pub async fn save_runtime_config(
+        &self,
+        update: impl FnOnce(&mut MyConfig),
+    ) -> anyhow::Result<()> {
+        let mut saved = self.saved_config.write().await;
+        let mut config = saved.clone();
+        update(&mut config);
+        config.to_nvs(&mut *self.nvs.write().await)?;
+        *saved = config;
+        Ok(())
+    }
+
+    /// Replace the saved config, e.g. from `POST /config`
+    pub async fn save_config(&self, config: MyConfig) -> anyhow::Result<()> {
+        let mut saved = self.saved_config.write().await;
+        config.to_nvs(&mut *self.nvs.write().await)?;
+        *saved = config;
+        Ok(())
+    }
+
     pub async fn request_ap_mode_on_next_boot(&self) -> anyhow::Result<()> {
         self.nvs.write().await.set_u8(AP_MODE_NVS_KEY, 1)?;
         Ok(())
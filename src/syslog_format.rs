@@ -0,0 +1,129 @@
+// syslog_format.rs
+//
+// RFC 5424 message and RFC 6587 TCP framing for the syslog forwarding in
+// `logging.rs`, kept free of ESP-IDF so it is unit tested on the host.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Datelike, Utc};
+
+// local0
+pub const SYSLOG_FACILITY: u8 = 16;
+pub const SYSLOG_APP_NAME: &str = "esp32temp";
+
+pub struct SyslogRecord {
+    pub level: ::log::Level,
+    pub target: String,
+    pub timestamp: DateTime<Utc>,
+    pub message: String,
+}
+
+fn syslog_severity(level: ::log::Level) -> u8 {
+    match level {
+        ::log::Level::Error => 3,
+        ::log::Level::Warn => 4,
+        ::log::Level::Info => 6,
+        ::log::Level::Debug | ::log::Level::Trace => 7,
+    }
+}
+
+/// One RFC 5424 message, e.g.
+/// `<134>1 2024-01-01T12:00:00.000Z esp32temp-A0B1C2D3E4F5 esp32temp - measure - Sensor 28ff...`
+pub fn syslog_message(rec: &SyslogRecord, hostname: &str) -> String {
+    let pri = SYSLOG_FACILITY * 8 + syslog_severity(rec.level);
+    // NILVALUE until the clock has been set
+    let timestamp = if rec.timestamp.year() > 2020 {
+        rec.timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+    } else {
+        "-".to_string()
+    };
+    // module name as MSGID, printable ASCII only and at most 32 characters
+    let msgid = rec
+        .target
+        .rsplit("::")
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(32)
+        .collect::<String>();
+    let msgid = if msgid.is_empty() { "-" } else { &msgid };
+
+    let mut msg = String::with_capacity(rec.message.len() + 96);
+    let _ = write!(
+        msg,
+        "<{pri}>1 {timestamp} {hostname} {SYSLOG_APP_NAME} - {msgid} - {}",
+        rec.message
+    );
+    msg
+}
+
+/// Octet-counting framing for TCP (RFC 6587): the length in bytes, a space, the message
+pub fn syslog_tcp_frame(msg: &str) -> String {
+    format!("{} {msg}", msg.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike};
+
+    use super::*;
+
+    fn record(level: ::log::Level, target: &str, message: &str) -> SyslogRecord {
+        SyslogRecord {
+            level,
+            target: target.to_string(),
+            timestamp: Utc
+                .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+                .unwrap()
+                .with_nanosecond(7_000_000)
+                .unwrap(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn message_header() {
+        let rec = record(::log::Level::Info, "esp32temp::measure", "Sensor 28ff ok");
+        assert_eq!(
+            syslog_message(&rec, "esp32temp-A0B1C2D3E4F5"),
+            "<134>1 2024-01-01T12:00:00.007Z esp32temp-A0B1C2D3E4F5 esp32temp - measure - Sensor 28ff ok"
+        );
+        let rec = record(::log::Level::Error, "esp32temp::mqtt", "x");
+        assert!(syslog_message(&rec, "h").starts_with("<131>1 "));
+        let rec = record(::log::Level::Trace, "esp32temp::mqtt", "x");
+        assert!(syslog_message(&rec, "h").starts_with("<135>1 "));
+    }
+
+    #[test]
+    fn nil_timestamp_before_clock_set() {
+        let mut rec = record(::log::Level::Warn, "wifi", "up");
+        rec.timestamp = DateTime::<Utc>::UNIX_EPOCH;
+        assert_eq!(
+            syslog_message(&rec, "h"),
+            "<132>1 - h esp32temp - wifi - up"
+        );
+    }
+
+    #[test]
+    fn msgid_is_sanitized() {
+        let rec = record(::log::Level::Info, "", "m");
+        assert!(syslog_message(&rec, "h").ends_with(" esp32temp - - - m"));
+
+        let long = format!("a::b c\u{e9}{}", "x".repeat(40));
+        let rec = record(::log::Level::Info, &long, "m");
+        let msg = syslog_message(&rec, "h");
+        let msgid = msg.split(' ').nth(5).unwrap();
+        assert_eq!(msgid.len(), 32);
+        assert!(msgid.starts_with("bcxx"));
+    }
+
+    #[test]
+    fn tcp_frame_counts_bytes() {
+        assert_eq!(syslog_tcp_frame("<134>1 - h"), "10 <134>1 - h");
+        // multi-byte characters count by their UTF-8 length
+        assert_eq!(syslog_tcp_frame("\u{b0}C"), "3 \u{b0}C");
+    }
+}
+
+// EOF
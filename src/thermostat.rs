@@ -0,0 +1,249 @@
+// thermostat.rs
+//
+// On/off control of a heater or fan through a GPIO relay output, driven by one
+// configured sensor. The relay follows the setpoint with hysteresis, but never
+// switches again before its minimum on/off time has passed. Without a fresh
+// reading the relay goes to the configured failsafe state.
+
+use std::{fmt, time::Instant};
+
+use crate::*;
+
+const THERMO_TICK_S: u64 = 5;
+// a reading older than this many poll intervals counts as unavailable
+const THERMO_STALE_POLLS: i64 = 3;
+pub const THERMO_SETPOINT_MIN: f32 = -50.0;
+pub const THERMO_SETPOINT_MAX: f32 = 125.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThermoMode {
+    #[default]
+    Off,
+    Heat,
+    Cool,
+}
+
+impl ThermoMode {
+    pub const ALL: [ThermoMode; 3] = [ThermoMode::Off, ThermoMode::Heat, ThermoMode::Cool];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ThermoMode::Off => "off",
+            ThermoMode::Heat => "heat",
+            ThermoMode::Cool => "cool",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+impl fmt::Display for ThermoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct Thermostat {
+    relay: Option<PinDriver<'static, Output>>,
+    pub mode: ThermoMode,
+    pub setpoint: f32,
+    pub relay_on: bool,
+    pub failsafe: bool,
+    pub temperature: Option<f32>,
+    last_switch: Option<Instant>,
+}
+
+/// Thermostat status as reported over HTTP and MQTT, in the display unit
+#[derive(Clone, Debug, Serialize)]
+pub struct ThermostatStatus {
+    pub enabled: bool,
+    pub sensor: String,
+    pub mode: ThermoMode,
+    pub setpoint: f32,
+    pub hysteresis: f32,
+    pub unit: TempUnit,
+    pub temperature: Option<f32>,
+    pub relay_on: bool,
+    pub failsafe: bool,
+}
+
+/// Runtime change of mode and/or setpoint (setpoint in the display unit)
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ThermostatCommand {
+    pub mode: Option<ThermoMode>,
+    pub setpoint: Option<f32>,
+}
+
+impl Thermostat {
+    pub fn new(config: &MyConfig, relay: Option<PinDriver<'static, Output>>) -> Self {
+        let mut thermostat = Self {
+            relay,
+            mode: config.thermo_mode,
+            setpoint: config.thermo_setpoint,
+            relay_on: false,
+            failsafe: false,
+            temperature: None,
+            last_switch: None,
+        };
+        if let Err(e) = thermostat.drive(false, config.thermo_active_low) {
+            error!("Thermostat relay init error: {e:#}");
+        }
+        thermostat
+    }
+
+    pub fn status(&self, config: &MyConfig) -> ThermostatStatus {
+        let unit = config.unit;
+        ThermostatStatus {
+            enabled: config.thermo_enable,
+            sensor: config.thermo_sensor.clone(),
+            mode: self.mode,
+            setpoint: unit.from_celsius(self.setpoint),
+            hysteresis: unit.delta_from_celsius(config.thermo_hysteresis),
+            unit,
+            temperature: self.temperature.map(|t| unit.from_celsius(t)),
+            relay_on: self.relay_on,
+            failsafe: self.failsafe,
+        }
+    }
+
+    fn drive(&mut self, on: bool, active_low: bool) -> anyhow::Result<()> {
+        if let Some(relay) = self.relay.as_mut() {
+            if on != active_low {
+                relay.set_high()?;
+            } else {
+                relay.set_low()?;
+            }
+        }
+        self.relay_on = on;
+        Ok(())
+    }
+
+    // Evaluate one control step, returns true if the relay was switched
+    fn step(&mut self, config: &MyConfig, temperature: Option<f32>) -> anyhow::Result<bool> {
+        self.temperature = temperature;
+        self.failsafe = temperature.is_none() && self.mode != ThermoMode::Off;
+
+        let half = config.thermo_hysteresis.abs() / 2.0;
+        let want = match (self.mode, temperature) {
+            (ThermoMode::Off, _) => false,
+            (_, None) => config.thermo_failsafe_on,
+            (ThermoMode::Heat, Some(t)) if t <= self.setpoint - half => true,
+            (ThermoMode::Heat, Some(t)) if t >= self.setpoint + half => false,
+            (ThermoMode::Cool, Some(t)) if t >= self.setpoint + half => true,
+            (ThermoMode::Cool, Some(t)) if t <= self.setpoint - half => false,
+            // inside the hysteresis band
+            _ => self.relay_on,
+        };
+        if want == self.relay_on {
+            return Ok(false);
+        }
+
+        // switching off honors the minimum on time and vice versa, turning the mode off does not
+        let min_time = if self.relay_on {
+            config.thermo_min_on
+        } else {
+            config.thermo_min_off
+        };
+        let too_soon = self
+            .last_switch
+            .is_some_and(|last| last.elapsed() < Duration::from_secs(min_time));
+        if too_soon && self.mode != ThermoMode::Off {
+            return Ok(false);
+        }
+
+        self.drive(want, config.thermo_active_low)?;
+        self.last_switch = Some(Instant::now());
+        Ok(true)
+    }
+}
+
+pub async fn run_thermostat(state: Arc<Pin<Box<MyState>>>) -> anyhow::Result<()> {
+    if !state.config.thermo_enable {
+        info!("Thermostat is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    // in AP mode there are no readings and the relay stays in the failsafe state
+    let stale_s = THERMO_STALE_POLLS * state.config.delay.max(THERMO_TICK_S) as i64;
+    loop {
+        let now = Utc::now().timestamp();
+        let temperature = {
+            let data = state.data.read().await;
+            data.temperatures
+                .iter()
+                .find(|t| {
+                    t.value > NO_TEMP
+                        && t.sensor.eq_ignore_ascii_case(&state.config.thermo_sensor)
+                        && now - t.timestamp <= stale_s
+                })
+                .map(|t| t.value)
+        };
+
+        {
+            let mut thermostat = state.thermostat.write().await;
+            match thermostat.step(&state.config, temperature) {
+                Ok(true) => {
+                    info!(
+                        "Thermostat relay {} (mode {}, setpoint {}, temperature {temperature:?})",
+                        if thermostat.relay_on { "on" } else { "off" },
+                        thermostat.mode,
+                        thermostat.setpoint
+                    );
+                }
+                Ok(false) => {}
+                Err(e) => error!("Thermostat relay error: {e:#}"),
+            }
+        }
+
+        sleep(Duration::from_secs(THERMO_TICK_S)).await;
+    }
+}
+
+/// Apply a mode/setpoint change from HTTP, MQTT or ESPHome and persist it.
+pub async fn set_thermostat(
+    state: &MyState,
+    cmd: ThermostatCommand,
+) -> anyhow::Result<ThermostatStatus> {
+    let setpoint = cmd.setpoint.map(|s| state.config.unit.to_celsius(s));
+    if setpoint.is_some_and(|s| !(THERMO_SETPOINT_MIN..=THERMO_SETPOINT_MAX).contains(&s)) {
+        bail!("Setpoint out of range");
+    }
+
+    let (status, mode, setpoint) = {
+        let mut thermostat = state.thermostat.write().await;
+        if let Some(mode) = cmd.mode {
+            thermostat.mode = mode;
+        }
+        if let Some(s) = setpoint {
+            thermostat.setpoint = s;
+        }
+        info!(
+            "Thermostat set to mode {}, setpoint {}",
+            thermostat.mode, thermostat.setpoint
+        );
+        (
+            thermostat.status(&state.config),
+            thermostat.mode,
+            thermostat.setpoint,
+        )
+    };
+
+    state
+        .save_runtime_config(|config| {
+            config.thermo_mode = mode;
+            config.thermo_setpoint = setpoint;
+        })
+        .await?;
+    // publish the new state on MQTT right away
+    *state.fresh_data.write().await = true;
+    Ok(status)
+}
+
+// EOF
@@ -0,0 +1,176 @@
+// trend.rs
+//
+// Per-sensor rate of change, computed as the least squares slope over the
+// last `trend_samples` readings, so a single noisy sample does not flip the trend.
+
+use std::{collections::VecDeque, time::Instant};
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trend {
+    Unknown,
+    Rising,
+    Falling,
+    Steady,
+}
+
+impl Trend {
+    pub fn from_rate(rate_min: Option<f32>, steady_band: f32) -> Self {
+        match rate_min {
+            None => Trend::Unknown,
+            Some(r) if r >= steady_band => Trend::Rising,
+            Some(r) if r <= -steady_band => Trend::Falling,
+            Some(_) => Trend::Steady,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Trend::Unknown => "unknown",
+            Trend::Rising => "rising",
+            Trend::Falling => "falling",
+            Trend::Steady => "steady",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SensorHistory {
+    samples: VecDeque<(Instant, f32)>,
+    capacity: usize,
+}
+
+impl SensorHistory {
+    pub fn new(capacity: usize) -> Self {
+        // two points are needed for a slope
+        let capacity = capacity.max(2);
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, at: Instant, value: f32) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, value));
+    }
+
+    /// Rate of change in degrees per minute, `None` until there are at least two samples
+    pub fn rate_per_min(&self) -> Option<f32> {
+        let (t0, _) = *self.samples.front()?;
+        if self.samples.len() < 2 {
+            return None;
+        }
+
+        // minutes since the oldest sample, centered on the means so that the sums
+        // stay small whatever the temperature and the span of the window
+        let n = self.samples.len() as f64;
+        let points = self
+            .samples
+            .iter()
+            .map(|(t, v)| (t.duration_since(t0).as_secs_f64() / 60.0, f64::from(*v)))
+            .collect::<Vec<_>>();
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let (sxx, sxy) = points.iter().fold((0.0, 0.0), |(sxx, sxy), (x, y)| {
+            let (dx, dy) = (x - mean_x, y - mean_y);
+            (sxx + dx * dx, sxy + dx * dy)
+        });
+
+        if sxx <= f64::EPSILON {
+            return None;
+        }
+        Some((sxy / sxx) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // one sample per minute from `start`
+    fn history(capacity: usize, values: &[f32]) -> (SensorHistory, Instant) {
+        let start = Instant::now();
+        let mut h = SensorHistory::new(capacity);
+        for (i, v) in values.iter().enumerate() {
+            h.push(start + Duration::from_secs(60 * i as u64), *v);
+        }
+        (h, start)
+    }
+
+    fn assert_rate(h: &SensorHistory, expected: f32) {
+        let rate = h.rate_per_min().unwrap();
+        assert!(
+            (rate - expected).abs() < 1e-4,
+            "rate {rate}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn rising() {
+        let (h, _) = history(5, &[20.0, 20.5, 21.0, 21.5]);
+        assert_rate(&h, 0.5);
+        assert_eq!(Trend::from_rate(h.rate_per_min(), 0.1), Trend::Rising);
+    }
+
+    #[test]
+    fn falling() {
+        let (h, _) = history(5, &[-18.0, -18.25, -18.5, -18.75, -19.0]);
+        assert_rate(&h, -0.25);
+        assert_eq!(Trend::from_rate(h.rate_per_min(), 0.1), Trend::Falling);
+    }
+
+    #[test]
+    fn flat_and_noisy() {
+        let (h, _) = history(5, &[85.0, 85.0, 85.0]);
+        assert_rate(&h, 0.0);
+        let (h, _) = history(5, &[21.0, 21.0625, 21.0, 21.0625, 21.0]);
+        assert_eq!(Trend::from_rate(h.rate_per_min(), 0.1), Trend::Steady);
+    }
+
+    #[test]
+    fn single_sample() {
+        let (h, start) = history(5, &[]);
+        assert_eq!(h.rate_per_min(), None);
+        let (h, _) = history(5, &[21.0]);
+        assert_eq!(h.rate_per_min(), None);
+        assert_eq!(Trend::from_rate(None, 0.1), Trend::Unknown);
+        // two samples at the same time give no slope either
+        let mut h = SensorHistory::new(5);
+        h.push(start, 21.0);
+        h.push(start, 22.0);
+        assert_eq!(h.rate_per_min(), None);
+    }
+
+    #[test]
+    fn window_eviction() {
+        // the early rise has left the three sample window
+        let (h, _) = history(3, &[10.0, 15.0, 20.0, 20.0, 20.0]);
+        assert_rate(&h, 0.0);
+        // capacity is at least two
+        let (h, _) = history(1, &[10.0, 11.0, 13.0]);
+        assert_rate(&h, 2.0);
+    }
+
+    #[test]
+    fn long_window_high_temperature() {
+        // a day of 10 minute samples near 100 degrees, rising slowly
+        let start = Instant::now();
+        let mut h = SensorHistory::new(144);
+        for i in 0..144u64 {
+            h.push(
+                start + Duration::from_secs(600 * i),
+                100.0 + 0.001 * (10 * i) as f32,
+            );
+        }
+        assert_rate(&h, 0.001);
+    }
+}
+
+// EOF
@@ -0,0 +1,92 @@
+// units.rs
+//
+// Temperatures are measured and processed in Celsius, conversion to the
+// configured display unit happens only when data leaves the device.
+
+use std::fmt;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TempUnit {
+    #[default]
+    #[serde(rename = "C", alias = "c")]
+    Celsius,
+    #[serde(rename = "F", alias = "f")]
+    Fahrenheit,
+    #[serde(rename = "K", alias = "k")]
+    Kelvin,
+}
+
+impl TempUnit {
+    /// Convert an absolute temperature from Celsius
+    pub fn from_celsius(self, value: f32) -> f32 {
+        match self {
+            TempUnit::Celsius => value,
+            TempUnit::Fahrenheit => value * 1.8 + 32.0,
+            TempUnit::Kelvin => value + 273.15,
+        }
+    }
+
+    /// Convert an absolute temperature to Celsius
+    pub fn to_celsius(self, value: f32) -> f32 {
+        match self {
+            TempUnit::Celsius => value,
+            TempUnit::Fahrenheit => (value - 32.0) / 1.8,
+            TempUnit::Kelvin => value - 273.15,
+        }
+    }
+
+    /// Convert a temperature difference (rates, degree-days) from Celsius
+    pub fn delta_from_celsius(self, value: f32) -> f32 {
+        match self {
+            TempUnit::Fahrenheit => value * 1.8,
+            TempUnit::Celsius | TempUnit::Kelvin => value,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TempUnit::Celsius => "\u{00B0}C",
+            TempUnit::Fahrenheit => "\u{00B0}F",
+            TempUnit::Kelvin => "K",
+        }
+    }
+}
+
+impl fmt::Display for TempUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TempUnit::Celsius => "C",
+            TempUnit::Fahrenheit => "F",
+            TempUnit::Kelvin => "K",
+        };
+        f.write_str(s)
+    }
+}
+
+impl TempData {
+    pub fn in_unit(&self, unit: TempUnit) -> Self {
+        Self {
+            value: unit.from_celsius(self.value),
+            rate_min: self.rate_min.map(|r| unit.delta_from_celsius(r)),
+            rate_h: self.rate_h.map(|r| unit.delta_from_celsius(r)),
+            ..self.clone()
+        }
+    }
+}
+
+impl DegreeDays {
+    pub fn in_unit(&self, unit: TempUnit) -> DegreeDayTotals {
+        DegreeDayTotals {
+            heat_today: unit.delta_from_celsius(self.heat_today),
+            cool_today: unit.delta_from_celsius(self.cool_today),
+            heat_yesterday: unit.delta_from_celsius(self.heat_yesterday),
+            cool_yesterday: unit.delta_from_celsius(self.cool_yesterday),
+            heat_month: unit.delta_from_celsius(self.heat_month),
+            cool_month: unit.delta_from_celsius(self.cool_month),
+        }
+    }
+}
+
+// EOF
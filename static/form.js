@@ -6,6 +6,8 @@ document.addEventListener("DOMContentLoaded", function () {
     initUptime();
     initDetectedSensors();
     initTemperatures();
+    initAlerts();
+    initLog();
 });
 
 function bindForm(name, handler) {
@@ -123,7 +125,7 @@ async function updateTemperatures() {
     try {
         const response = await fetch("/temp");
         const json = await response.json();
-        let rows = "<tr><th>IO pin</th><th>Sensor</th><th>Value (C)</th></tr>\n";
+        let rows = `<tr><th>IO pin</th><th>Sensor</th><th>Value (${json.unit})</th></tr>\n`;
         json.temperatures.forEach((temp) => {
             rows += `<tr><td><code>${temp.iopin}</code></td><td>${temp.sensor}</td><td class="temperature-value">${temp.value}</td></tr>\n`;
         });
@@ -141,6 +143,77 @@ function initTemperatures() {
     window.setInterval(updateTemperatures, 60e3);
 }
 
+async function updateAlerts() {
+    const node = document.getElementById("alerts");
+    if (!node) return;
+
+    try {
+        const response = await fetch("/alerts");
+        const json = await response.json();
+
+        if (!json.alerts.length) {
+            node.innerHTML = '<div class="table-meta">No alert rules configured</div>';
+            return;
+        }
+
+        const table = document.createElement("table");
+        const header = table.insertRow();
+        ["Alert", "Sensor", "Rule", "Value", "State"].forEach((title) => {
+            const th = document.createElement("th");
+            th.textContent = title;
+            header.appendChild(th);
+        });
+        json.alerts.forEach((alert) => {
+            const threshold = alert.threshold === null ? "" : ` ${alert.threshold}`;
+            const value = alert.value === null ? "-" : alert.value;
+            const since = alert.since ? ` since ${new Date(alert.since * 1000).toLocaleString()}` : "";
+            const state = alert.active ? "FIRING" : "ok";
+            // names and sensor ids come from the configuration, so they are set as text only
+            const row = table.insertRow();
+            if (alert.active) row.className = "alert-active";
+            [alert.name, alert.sensor, `${alert.kind}${threshold}`, value, `${state}${since}`].forEach((text) => {
+                row.insertCell().textContent = text;
+            });
+        });
+        const firing = json.alerts.filter((alert) => alert.active).length;
+        const meta = document.createElement("div");
+        meta.className = "table-meta";
+        const count = document.createElement("b");
+        count.textContent = firing;
+        meta.append("Firing: ", count, ` of ${json.alerts.length}`);
+        node.replaceChildren(meta, table);
+    } catch (_error) {
+        node.textContent = "Alert state unavailable";
+    }
+}
+
+function initAlerts() {
+    if (!document.getElementById("alerts")) return;
+    updateAlerts();
+    window.setInterval(updateAlerts, 30e3);
+}
+
+async function updateLog() {
+    const node = document.getElementById("log");
+    if (!node) return;
+
+    try {
+        const response = await fetch("/log?lines=200");
+        // keep the scroll position unless already at the bottom
+        const atBottom = node.scrollTop + node.clientHeight >= node.scrollHeight - 4;
+        node.textContent = await response.text();
+        if (atBottom) node.scrollTop = node.scrollHeight;
+    } catch (_error) {
+        node.textContent = "Log unavailable";
+    }
+}
+
+function initLog() {
+    if (!document.getElementById("log")) return;
+    updateLog();
+    window.setInterval(updateLog, 10e3);
+}
+
 const handleCfgSubmit = async (event) => {
     event.preventDefault();
     const form = event.currentTarget;
@@ -199,10 +272,59 @@ const postCfgDataAsJson = async ({url, formData}) => {
     formObj.v4mask = parseInt(formObj.v4mask, 10);
     formObj.retries = parseInt(formObj.retries, 10);
     formObj.delay = parseInt(formObj.delay, 10);
+    formObj.delay_fast = parseInt(formObj.delay_fast, 10);
+    formObj.rate_threshold = parseFloat(formObj.rate_threshold);
+    formObj.trend_samples = parseInt(formObj.trend_samples, 10);
+    formObj.trend_band = parseFloat(formObj.trend_band);
+    formObj.dd_heat_base = parseFloat(formObj.dd_heat_base);
+    formObj.dd_cool_base = parseFloat(formObj.dd_cool_base);
+    formObj.thermo_mode = formObj.thermo_mode.trim().toLowerCase();
+    formObj.thermo_setpoint = parseFloat(formObj.thermo_setpoint);
+    formObj.thermo_hysteresis = parseFloat(formObj.thermo_hysteresis);
+    formObj.thermo_min_on = parseInt(formObj.thermo_min_on, 10);
+    formObj.thermo_min_off = parseInt(formObj.thermo_min_off, 10);
+    formObj.pwm_freq = parseInt(formObj.pwm_freq, 10);
+    formObj.pwm_setpoint = parseFloat(formObj.pwm_setpoint);
+    formObj.pwm_kp = parseFloat(formObj.pwm_kp);
+    formObj.pwm_ki = parseFloat(formObj.pwm_ki);
+    formObj.pwm_kd = parseFloat(formObj.pwm_kd);
+    formObj.pwm_min = parseFloat(formObj.pwm_min);
+    formObj.pwm_max = parseFloat(formObj.pwm_max);
+    formObj.pwm_failsafe = parseFloat(formObj.pwm_failsafe);
+    formObj.alert_hysteresis = parseFloat(formObj.alert_hysteresis);
+    formObj.alert_holdoff = parseInt(formObj.alert_holdoff, 10);
+    formObj.influx_batch = parseInt(formObj.influx_batch, 10);
+    formObj.push_method = formObj.push_method.trim().toUpperCase();
+    formObj.push_expect = parseInt(formObj.push_expect, 10);
+    formObj.graphite_port = parseInt(formObj.graphite_port, 10);
+    formObj.statsd_port = parseInt(formObj.statsd_port, 10);
+    formObj.announce_port = parseInt(formObj.announce_port, 10);
+    formObj.announce_ttl = parseInt(formObj.announce_ttl, 10);
+    formObj.syslog_port = parseInt(formObj.syslog_port, 10);
+    formObj.syslog_level = formObj.syslog_level.trim().toLowerCase();
+    formObj.syslog_rate = parseInt(formObj.syslog_rate, 10);
+    formObj.log_level = formObj.log_level.trim().toLowerCase();
+    formObj.ds2482_addr = parseInt(formObj.ds2482_addr, 10);
     formObj.wifi_wpa2ent = (formObj.wifi_wpa2ent === "on");
     formObj.v4dhcp = (formObj.v4dhcp === "on");
     formObj.esphome_enable = (formObj.esphome_enable === "on");
     formObj.mqtt_enable = (formObj.mqtt_enable === "on");
+    formObj.mqtt_retain = (formObj.mqtt_retain === "on");
+    formObj.mqtt_discovery = (formObj.mqtt_discovery === "on");
+    formObj.influx_enable = (formObj.influx_enable === "on");
+    formObj.influx_v2 = (formObj.influx_v2 === "on");
+    formObj.push_enable = (formObj.push_enable === "on");
+    formObj.push_per_sensor = (formObj.push_per_sensor === "on");
+    formObj.graphite_enable = (formObj.graphite_enable === "on");
+    formObj.statsd_enable = (formObj.statsd_enable === "on");
+    formObj.announce_enable = (formObj.announce_enable === "on");
+    formObj.syslog_enable = (formObj.syslog_enable === "on");
+    formObj.syslog_tcp = (formObj.syslog_tcp === "on");
+    formObj.thermo_enable = (formObj.thermo_enable === "on");
+    formObj.thermo_active_low = (formObj.thermo_active_low === "on");
+    formObj.thermo_failsafe_on = (formObj.thermo_failsafe_on === "on");
+    formObj.pwm_enable = (formObj.pwm_enable === "on");
+    formObj.pwm_reverse = (formObj.pwm_reverse === "on");
 
     return fetchPayloadOrError(url, {
         method: "POST",